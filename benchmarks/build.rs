@@ -0,0 +1,78 @@
+//! Records which versions of arroy and of this repository were compiled so that
+//! they can be written in the manifest of every result file.
+//!
+//! The version of arroy 0.5 is only recorded when its `arroy-0-5` feature is enabled.
+
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+
+/// The sources compiled in the benchmarks, a change to them makes the commit dirty.
+const SOURCES: &[&str] = &["src", "build.rs", "Cargo.toml", "../matfile", "../Cargo.toml"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=../Cargo.lock");
+    // A new commit moves the branch HEAD points to, which is either a ref file or a packed ref.
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    let head = fs::read_to_string("../.git/HEAD").unwrap_or_default();
+    if let Some(branch) = head.trim().strip_prefix("ref: ") {
+        println!("cargo:rerun-if-changed=../.git/{branch}");
+    }
+    if Path::new("../.git/packed-refs").exists() {
+        println!("cargo:rerun-if-changed=../.git/packed-refs");
+    }
+    for source in SOURCES {
+        println!("cargo:rerun-if-changed={source}");
+    }
+
+    let lock = fs::read_to_string("../Cargo.lock").unwrap_or_default();
+    // The lock file lists arroy 0.5 even when the `arroy-0-5` feature is disabled.
+    let (arroy_0_5, arroy): (Vec<_>, Vec<_>) =
+        locked_versions(&lock, "arroy").into_iter().partition(|v| v.starts_with("0.5."));
+    println!("cargo:rustc-env=BENCHMARKS_ARROY_VERSION={}", join_versions(&arroy));
+    if env::var_os("CARGO_FEATURE_ARROY_0_5").is_some() {
+        println!("cargo:rustc-env=BENCHMARKS_ARROY_0_5_VERSION={}", join_versions(&arroy_0_5));
+    }
+
+    let commit = git(&["rev-parse", "HEAD"]).unwrap_or_else(|| String::from("unknown"));
+    let mut status = vec!["status", "--porcelain", "--"];
+    status.extend(SOURCES);
+    let dirty = git(&status).is_some_and(|s| !s.is_empty());
+    let commit = if dirty { format!("{commit}-dirty") } else { commit };
+    println!("cargo:rustc-env=BENCHMARKS_GIT_COMMIT={commit}");
+}
+
+/// Returns the version and source of every package with this name in the lock file.
+fn locked_versions(lock: &str, name: &str) -> Vec<String> {
+    lock.split("[[package]]")
+        .filter(|package| package.lines().any(|line| line == format!("name = \"{name}\"")))
+        .filter_map(|package| {
+            let field = |field: &str| {
+                package.lines().find_map(|line| {
+                    line.strip_prefix(field)?.strip_prefix(" = \"")?.strip_suffix('"')
+                })
+            };
+            let version = field("version")?;
+            match field("source") {
+                Some(source) if !source.starts_with("registry+") => {
+                    Some(format!("{version} ({source})"))
+                }
+                Some(_) => Some(version.to_string()),
+                None => Some(format!("{version} (path)")),
+            }
+        })
+        .collect()
+}
+
+fn join_versions(versions: &[String]) -> String {
+    if versions.is_empty() {
+        String::from("unknown")
+    } else {
+        versions.join(", ")
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
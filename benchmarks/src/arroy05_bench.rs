@@ -0,0 +1,203 @@
+//! The same benchmark as [`crate::arroy_bench`] but compiled against arroy 0.5.
+//!
+//! This version of arroy doesn't let us limit the memory used to build the trees
+//! nor report its progress.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use arroy_0_5::internals::{self, NodeCodec};
+use arroy_0_5::{Database, Distance, ItemId, Writer};
+use heed_0_20::EnvOpenOptions;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use roaring::RoaringBitmap;
+
+use crate::scenarios::*;
+use crate::{IndexingMetrics, Recall, SearchResult, VectorDataset};
+const TWENTY_HUNDRED_MIB: usize = 2000 * 1024 * 1024 * 1024;
+
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_and_run<D, F, R>(
+    dataset: &dyn VectorDataset,
+    count: usize,
+    nb_trees: Option<usize>,
+    number_of_chunks: usize,
+    sleep_between_chunks: usize,
+    memory: usize,
+    arroy_seed: u64,
+    _verbose: bool,
+    execute: F,
+) -> R
+where
+    D: Distance,
+    F: FnOnce(IndexingMetrics, &heed_0_20::Env, Database<D>) -> R,
+{
+    let dimensions = dataset.dimensions();
+    if memory != usize::MAX {
+        tracing::warn!("arroy 0.5 cannot limit the memory used for indexing");
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let env =
+        unsafe { EnvOpenOptions::new().map_size(TWENTY_HUNDRED_MIB).open(dir.path()) }.unwrap();
+
+    let mut arroy_seed = StdRng::seed_from_u64(arroy_seed);
+    let mut wtxn = env.write_txn().unwrap();
+    let database =
+        env.create_database::<internals::KeyCodec, NodeCodec<D>>(&mut wtxn, None).unwrap();
+    wtxn.commit().unwrap();
+
+    let metrics = load_into_arroy(
+        &mut arroy_seed,
+        &env,
+        database,
+        dimensions,
+        dataset,
+        count,
+        number_of_chunks,
+        nb_trees,
+        sleep_between_chunks,
+    );
+
+    (execute)(metrics, &env, database)
+}
+
+pub fn run_scenarios<D: Distance>(
+    env: &heed_0_20::Env,
+    search: &[&ScenarioSearch],
+    queries: &[(
+        Option<u32>,
+        &[f32],
+        HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>,
+    )],
+    recall_tested: &[usize],
+    database: Database<D>,
+) -> Vec<SearchResult> {
+    let mut results = Vec::new();
+
+    for &&search in search {
+        let ScenarioSearch { oversampling, filtering } = search;
+        let mut time_to_search = Duration::default();
+        let mut recalls = Vec::new();
+        for &number_fetched in recall_tested {
+            let (correctly_retrieved, duration) = queries
+                .par_iter()
+                .map(|(id, target, relevants)| {
+                    let rtxn = env.read_txn().unwrap();
+                    let reader = arroy_0_5::Reader::open(&rtxn, 0, database).unwrap();
+
+                    let (candidates, relevants) = &relevants[&filtering];
+                    // Only keep the top number fetched documents.
+                    let relevants = relevants.get(..number_fetched).unwrap_or(relevants);
+
+                    let now = std::time::Instant::now();
+                    let mut nns = reader.nns(number_fetched);
+                    if let Some(oversampling) = oversampling.to_non_zero_usize() {
+                        nns.oversampling(oversampling);
+                    }
+                    if let Some(candidates) = candidates.as_ref() {
+                        nns.candidates(candidates);
+                    }
+                    // The imported queries are not indexed and are searched by vector.
+                    let arroy_answer = match id {
+                        Some(id) => nns.by_item(&rtxn, *id).unwrap().unwrap(),
+                        None => nns.by_vector(&rtxn, target).unwrap(),
+                    };
+                    let elapsed = now.elapsed();
+
+                    let mut correctly_retrieved = Some(0);
+                    for (id, _dist) in arroy_answer {
+                        if relevants.contains(&id) {
+                            if let Some(cr) = &mut correctly_retrieved {
+                                *cr += 1;
+                            }
+                        } else if let Some(cand) = candidates.as_ref() {
+                            // We set the counter to -1 if we return a filtered out candidated
+                            if !cand.contains(id) {
+                                correctly_retrieved = None;
+                            }
+                        }
+                    }
+
+                    (correctly_retrieved, elapsed)
+                })
+                .reduce(
+                    || (Some(0), Duration::default()),
+                    |(aanswer, aduration), (banswer, bduration)| {
+                        (aanswer.zip(banswer).map(|(a, b)| a + b), aduration + bduration)
+                    },
+                );
+
+            time_to_search += duration;
+            // If non-candidate documents are returned we show a recall of -1
+            let recall = correctly_retrieved
+                .map_or(-1.0, |cr| cr as f32 / (number_fetched as f32 * queries.len() as f32));
+            recalls.push((number_fetched, Recall(recall)));
+        }
+
+        results.push(SearchResult { search, recalls, time_to_search });
+    }
+
+    results
+}
+
+#[allow(clippy::too_many_arguments)]
+fn load_into_arroy<D: Distance>(
+    rng: &mut StdRng,
+    env: &heed_0_20::Env,
+    database: Database<D>,
+    dimensions: usize,
+    dataset: &dyn VectorDataset,
+    count: usize,
+    number_of_chunks: usize,
+    nb_trees: Option<usize>,
+    sleep_between_chunks: usize,
+) -> IndexingMetrics {
+    let mut metrics = IndexingMetrics::new();
+    let avg_chunk_size = count / number_of_chunks;
+    let mut nb_vectors = 0;
+
+    for start in (0..count).step_by(avg_chunk_size) {
+        let chunk = start..(start + avg_chunk_size).min(count);
+        if sleep_between_chunks != 0 {
+            std::thread::sleep(Duration::from_secs(sleep_between_chunks as u64));
+        }
+        tracing::info!("Inserting chunk of size {} in arroy 0.5", chunk.len());
+        let mut wtxn = env.write_txn().unwrap();
+        metrics.start_insertion();
+        let writer = Writer::<D>::new(database, 0, dimensions);
+        // The vectors are converted to f32 one at a time, they are only borrowed from
+        // the memory mapped files that already store f32.
+        for i in chunk.clone() {
+            let vector = dataset.vector(i).unwrap();
+            assert_eq!(vector.len(), dimensions);
+            writer.add_item(&mut wtxn, i as ItemId, &vector).unwrap();
+        }
+        metrics.end_insertion();
+
+        tracing::info!("Starts building the trees");
+
+        let mut builder = writer.builder(rng);
+        if let Some(nb_trees) = nb_trees {
+            builder.n_trees(nb_trees);
+        }
+        metrics.start_building();
+        builder.build(&mut wtxn).unwrap();
+        metrics.end_building();
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let reader = arroy_0_5::Reader::open(&rtxn, 0, database).unwrap();
+        metrics.new_nb_trees(reader.n_trees());
+        drop(rtxn);
+
+        nb_vectors += chunk.len();
+        metrics.new_nb_vectors(nb_vectors);
+        metrics.new_database_size(env.non_free_pages_size().unwrap() as usize);
+    }
+
+    metrics.end();
+    metrics
+}
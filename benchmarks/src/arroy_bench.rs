@@ -5,83 +5,86 @@ use std::time::Duration;
 
 use arroy::internals::{self, NodeCodec};
 use arroy::{Database, Distance, ItemId, Writer, WriterProgress};
-use byte_unit::{Byte, UnitType};
 use heed::EnvOpenOptions;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use roaring::RoaringBitmap;
 
-use crate::Recall;
-use crate::{scenarios::*, IndexingMetrics};
+use crate::scenarios::*;
+use crate::{IndexingMetrics, Recall, SearchResult, VectorDataset};
 const TWENTY_HUNDRED_MIB: usize = 2000 * 1024 * 1024 * 1024;
 
-pub fn prepare_and_run<D, F>(
-    line: &mut String,
-    points: &[(u32, &[f32])],
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_and_run<D, F, R>(
+    dataset: &dyn VectorDataset,
+    count: usize,
     nb_trees: Option<usize>,
     number_of_chunks: usize,
     sleep_between_chunks: usize,
     memory: usize,
+    arroy_seed: u64,
     verbose: bool,
     execute: F,
-) where
+) -> R
+where
     D: Distance,
-    F: FnOnce(&mut String, &IndexingMetrics, &heed::Env, Database<D>),
+    F: FnOnce(IndexingMetrics, &heed::Env, Database<D>) -> R,
 {
-    let dimensions = points[0].1.len();
+    let dimensions = dataset.dimensions();
 
     let dir = tempfile::tempdir().unwrap();
     let env =
         unsafe { EnvOpenOptions::new().map_size(TWENTY_HUNDRED_MIB).open(dir.path()) }.unwrap();
 
-    let mut arroy_seed = StdRng::seed_from_u64(13);
+    let mut arroy_seed = StdRng::seed_from_u64(arroy_seed);
     let mut wtxn = env.write_txn().unwrap();
     let database =
         env.create_database::<internals::KeyCodec, NodeCodec<D>>(&mut wtxn, None).unwrap();
     wtxn.commit().unwrap();
 
-    let duration = load_into_arroy(
-        line,
+    let metrics = load_into_arroy(
         &mut arroy_seed,
         &env,
         database,
         dimensions,
         memory,
-        points,
+        dataset,
+        count,
         number_of_chunks,
         nb_trees,
         sleep_between_chunks,
         verbose,
     );
 
-    (execute)(line, &duration, &env, database);
+    (execute)(metrics, &env, database)
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn run_scenarios<D: Distance>(
-    line: &mut String,
     env: &heed::Env,
-    time_to_index: &IndexingMetrics,
-    distance: &ScenarioDistance,
-    number_of_chunks: usize,
     search: &[&ScenarioSearch],
-    queries: &[(&u32, &&[f32], HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>)],
+    queries: &[(
+        Option<u32>,
+        &[f32],
+        HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>,
+    )],
     recall_tested: &[usize],
     database: arroy::Database<D>,
-) {
-    let mut recalls = Vec::new();
+) -> Vec<SearchResult> {
+    let mut results = Vec::new();
 
-    for ScenarioSearch { oversampling, filtering } in search {
+    for &&search in search {
+        let ScenarioSearch { oversampling, filtering } = search;
         let mut time_to_search = Duration::default();
+        let mut recalls = Vec::new();
         for &number_fetched in recall_tested {
             let (correctly_retrieved, duration) = queries
                 .par_iter()
-                .map(|(&id, _target, relevants)| {
+                .map(|(id, target, relevants)| {
                     let rtxn = env.read_txn().unwrap();
                     let reader = arroy::Reader::open(&rtxn, 0, database).unwrap();
 
-                    let (candidates, relevants) = &relevants[filtering];
+                    let (candidates, relevants) = &relevants[&filtering];
                     // Only keep the top number fetched documents.
                     let relevants = relevants.get(..number_fetched).unwrap_or(relevants);
 
@@ -93,7 +96,11 @@ pub fn run_scenarios<D: Distance>(
                     if let Some(candidates) = candidates.as_ref() {
                         nns.candidates(candidates);
                     }
-                    let arroy_answer = nns.by_item(&rtxn, id).unwrap().unwrap();
+                    // The imported queries are not indexed and are searched by vector.
+                    let arroy_answer = match id {
+                        Some(id) => nns.by_item(&rtxn, *id).unwrap().unwrap(),
+                        None => nns.by_vector(&rtxn, target).unwrap(),
+                    };
                     let elapsed = now.elapsed();
 
                     let mut correctly_retrieved = Some(0);
@@ -121,35 +128,33 @@ pub fn run_scenarios<D: Distance>(
 
             time_to_search += duration;
             // If non-candidate documents are returned we show a recall of -1
-            let recall =
-                correctly_retrieved.map_or(-1.0, |cr| cr as f32 / (number_fetched as f32 * 100.0));
-            recalls.push(Recall(recall));
+            let recall = correctly_retrieved
+                .map_or(-1.0, |cr| cr as f32 / (number_fetched as f32 * queries.len() as f32));
+            recalls.push((number_fetched, Recall(recall)));
         }
-    }
 
-    let recall_score = recalls.iter().map(|r| r.0).sum::<f32>() / recalls.len() as f32;
-    line.push_str(&format!("{recall_score:#.2},"));
-    for recall in &recalls {
-        line.push_str(&format!("{:#.2},", recall.0));
+        results.push(SearchResult { search, recalls, time_to_search });
     }
+
+    results
 }
 
 #[allow(clippy::too_many_arguments)]
 fn load_into_arroy<D: arroy::Distance>(
-    line: &mut String,
     rng: &mut StdRng,
     env: &heed::Env,
     database: Database<D>,
     dimensions: usize,
     memory: usize,
-    points: &[(ItemId, &[f32])],
+    dataset: &dyn VectorDataset,
+    count: usize,
     number_of_chunks: usize,
     nb_trees: Option<usize>,
     sleep_between_chunks: usize,
     verbose: bool,
 ) -> IndexingMetrics {
     let mut metrics = IndexingMetrics::new();
-    let avg_chunk_size = points.len() / number_of_chunks;
+    let avg_chunk_size = count / number_of_chunks;
     let mut nb_vectors = 0;
     let (progress_sender, progress_receiver) = std::sync::mpsc::channel();
 
@@ -157,17 +162,21 @@ fn load_into_arroy<D: arroy::Distance>(
         std::thread::spawn(move || log_progress(progress_receiver));
     }
 
-    for points in points.chunks(avg_chunk_size) {
+    for start in (0..count).step_by(avg_chunk_size) {
+        let chunk = start..(start + avg_chunk_size).min(count);
         if sleep_between_chunks != 0 {
             std::thread::sleep(Duration::from_secs(sleep_between_chunks as u64));
         }
-        tracing::info!("Inserting chunk of size {} in arroy", points.len());
+        tracing::info!("Inserting chunk of size {} in arroy", chunk.len());
         let mut wtxn = env.write_txn().unwrap();
         metrics.start_insertion();
         let writer = Writer::<D>::new(database, 0, dimensions);
-        for (i, vector) in points.iter() {
+        // The vectors are converted to f32 one at a time, they are only borrowed from
+        // the memory mapped files that already store f32.
+        for i in chunk.clone() {
+            let vector = dataset.vector(i).unwrap();
             assert_eq!(vector.len(), dimensions);
-            writer.add_item(&mut wtxn, *i, vector).unwrap();
+            writer.add_item(&mut wtxn, i as ItemId, &vector).unwrap();
         }
         metrics.end_insertion();
 
@@ -190,10 +199,9 @@ fn load_into_arroy<D: arroy::Distance>(
         metrics.new_nb_trees(reader.n_trees());
         drop(rtxn);
 
-        nb_vectors += points.len();
+        nb_vectors += chunk.len();
         metrics.new_nb_vectors(nb_vectors);
         metrics.new_database_size(env.non_free_pages_size().unwrap() as usize);
-        line.push_str(&format!("{},", env.non_free_pages_size().unwrap()));
     }
 
     metrics.end();
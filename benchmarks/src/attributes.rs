@@ -0,0 +1,298 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{ensure, Context};
+use itertools::Itertools;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::VectorDataset;
+
+/// The file storing the attributes of the vectors of a dataset, one JSON object per line,
+/// as written by `parquet2mat --id-column --attribute-columns`.
+pub fn attributes_path(dataset: &dyn VectorDataset) -> PathBuf {
+    dataset.path().with_extension("attributes.jsonl")
+}
+
+/// A predicate on the attributes of the documents, e.g. `lang = en AND year >= 2020`.
+///
+/// The conditions are separated by `AND`, in any case, outside of the quoted strings.
+/// The values are JSON literals, or strings when they don't parse as JSON. An array
+/// attribute is equal to a value when one of its elements is. The numbers are compared
+/// as numbers and the strings in lexicographic order, the other comparisons never match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AttributeFilter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    attribute: String,
+    operator: Operator,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Lower,
+    LowerOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    const ALL: [(&'static str, Operator); 6] = [
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LowerOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("=", Operator::Equal),
+        ("<", Operator::Lower),
+        (">", Operator::Greater),
+    ];
+
+    fn as_str(self) -> &'static str {
+        Operator::ALL.iter().find(|(_, operator)| *operator == self).unwrap().0
+    }
+}
+
+impl AttributeFilter {
+    /// Whether the attributes of a document match every condition.
+    pub fn matches(&self, attributes: &Map<String, Value>) -> bool {
+        self.conditions.iter().all(|condition| condition.matches(attributes))
+    }
+
+    /// The documents of the dataset whose attributes match, the attributes file is read once
+    /// and the documents of the smaller counts are the ones below them in this bitmap.
+    pub fn matching_documents(&self, dataset: &dyn VectorDataset) -> anyhow::Result<RoaringBitmap> {
+        let path = attributes_path(dataset);
+        let file = File::open(&path).with_context(|| {
+            format!("while opening the attributes of {} at {}", dataset.name(), path.display())
+        })?;
+        let mut matching = RoaringBitmap::new();
+        let mut lines = 0;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let attributes: Map<String, Value> =
+                serde_json::from_str(&line).with_context(|| {
+                    format!("while parsing the line {} of {}", i + 1, path.display())
+                })?;
+            if self.matches(&attributes) {
+                matching.insert(i as u32);
+            }
+            lines += 1;
+        }
+        ensure!(
+            lines == dataset.len(),
+            "{} has {lines} lines but {} has {} vectors",
+            path.display(),
+            dataset.name(),
+            dataset.len()
+        );
+        Ok(matching)
+    }
+}
+
+impl Condition {
+    fn matches(&self, attributes: &Map<String, Value>) -> bool {
+        let Some(attribute) = attributes.get(&self.attribute) else { return false };
+        match self.operator {
+            Operator::Equal => equals(attribute, &self.value),
+            Operator::NotEqual => !equals(attribute, &self.value),
+            operator => match (compare(attribute, &self.value), operator) {
+                (Some(ordering), Operator::Lower) => ordering.is_lt(),
+                (Some(ordering), Operator::LowerOrEqual) => ordering.is_le(),
+                (Some(ordering), Operator::Greater) => ordering.is_gt(),
+                (Some(ordering), Operator::GreaterOrEqual) => ordering.is_ge(),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn equals(attribute: &Value, value: &Value) -> bool {
+    match (attribute, value) {
+        (Value::Number(_), Value::Number(_)) => {
+            compare(attribute, value).is_some_and(Ordering::is_eq)
+        }
+        (Value::Array(elements), value) if !value.is_array() => {
+            elements.iter().any(|element| equals(element, value))
+        }
+        (attribute, value) => attribute == value,
+    }
+}
+
+fn compare(attribute: &Value, value: &Value) -> Option<Ordering> {
+    match (attribute, value) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for AttributeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conditions = self.conditions.iter().map(|Condition { attribute, operator, value }| {
+            format!("{attribute} {} {value}", operator.as_str())
+        });
+        write!(f, "{}", conditions.format(" AND "))
+    }
+}
+
+impl FromStr for AttributeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let conditions = split_conditions(s)
+            .into_iter()
+            .map(|condition| {
+                let Some(start) = condition.find(['=', '!', '<', '>']) else {
+                    return Err(format!(
+                        "`{condition}` is not a condition, expected `<attribute> <operator> <value>` with one of =, !=, <, <=, > or >="
+                    ));
+                };
+                let (attribute, rest) = condition.split_at(start);
+                let (operator, value) = Operator::ALL
+                    .iter()
+                    .find_map(|(name, operator)| Some((*operator, rest.strip_prefix(name)?)))
+                    .ok_or_else(|| format!("unknown operator in `{condition}`"))?;
+                let attribute = attribute.trim();
+                let value = value.trim();
+                if attribute.is_empty() || value.is_empty() {
+                    return Err(format!("`{condition}` must have an attribute and a value"));
+                }
+                let value = serde_json::from_str(value)
+                    .unwrap_or_else(|_| Value::String(value.to_string()));
+                Ok(Condition { attribute: attribute.to_string(), operator, value })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(AttributeFilter { conditions })
+    }
+}
+
+/// Splits the filter on the `AND` keywords, in any case, surrounded by whitespaces
+/// and outside of the double-quoted strings, e.g. `genre = "rock and roll"`. A trailing
+/// `AND` is split too so that the filter is rejected for its empty condition.
+fn split_conditions(s: &str) -> Vec<&str> {
+    let mut conditions = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c.is_whitespace() {
+            let rest = &s[i + c.len_utf8()..];
+            let is_and = rest.get(..3).is_some_and(|word| word.eq_ignore_ascii_case("and"))
+                && (rest[3..].is_empty() || rest[3..].starts_with(char::is_whitespace));
+            if is_and {
+                conditions.push(&s[start..i]);
+                // Skips the `and` keyword, the whitespaces around it are trimmed with the conditions.
+                for _ in 0..3 {
+                    chars.next();
+                }
+                start = i + c.len_utf8() + 3;
+            }
+        }
+    }
+    conditions.push(&s[start..]);
+    conditions
+}
+
+impl TryFrom<String> for AttributeFilter {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<AttributeFilter> for String {
+    fn from(filter: AttributeFilter) -> Self {
+        filter.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn condition(attribute: &str, operator: Operator, value: Value) -> Condition {
+        Condition { attribute: attribute.to_string(), operator, value }
+    }
+
+    #[test]
+    fn parse_conditions() {
+        let filter: AttributeFilter =
+            "lang = en AND year >= 2020 and tags != [1, 2]".parse().unwrap();
+        assert_eq!(
+            filter.conditions,
+            [
+                condition("lang", Operator::Equal, json!("en")),
+                condition("year", Operator::GreaterOrEqual, json!(2020)),
+                condition("tags", Operator::NotEqual, json!([1, 2])),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_and_inside_quoted_values() {
+        let filter: AttributeFilter =
+            r#"genre = "rock and roll" AND title = "say \"hi\" and leave""#.parse().unwrap();
+        assert_eq!(
+            filter.conditions,
+            [
+                condition("genre", Operator::Equal, json!("rock and roll")),
+                condition("title", Operator::Equal, json!(r#"say "hi" and leave"#)),
+            ]
+        );
+
+        // The `and` keyword must be surrounded by whitespaces.
+        let filter: AttributeFilter = "brand = android".parse().unwrap();
+        assert_eq!(filter.conditions, [condition("brand", Operator::Equal, json!("android"))]);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "lang = en AND year >= 2020",
+            r#"genre = "rock and roll" AND year < 1970.5"#,
+            "explicit != true and rating <= 3",
+        ] {
+            let filter: AttributeFilter = s.parse().unwrap();
+            assert_eq!(filter.to_string().parse::<AttributeFilter>().unwrap(), filter, "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_malformed_filters() {
+        for s in [
+            "",
+            "lang",
+            "lang ~ en",
+            "= en",
+            "lang =",
+            "lang = en AND",
+            "lang = en AND AND year = 1",
+        ] {
+            assert!(s.parse::<AttributeFilter>().is_err(), "`{s}` should not parse");
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use byte_unit::{Byte, UnitType};
+
+use crate::{Manifest, Recall};
+
+/// A CSV file generated by the benchmarks.
+#[derive(Debug, Clone)]
+pub struct ResultFile {
+    pub manifest: Option<Manifest>,
+    /// The columns that identify a scenario, in the order of the header.
+    pub key_columns: Vec<String>,
+    pub rows: Vec<ResultRow>,
+}
+
+/// A line of a result file.
+#[derive(Debug, Clone, Default)]
+pub struct ResultRow {
+    /// The columns that identify the scenario, e.g. `dataset`, `nb vectors` and `nb trees`,
+    /// with their values.
+    pub key: Vec<(String, String)>,
+    /// The version of the contender, written when several contenders were benchmarked.
+    pub contender_version: Option<String>,
+    /// The size of the database after the last chunk.
+    pub db_size: Option<u64>,
+    pub recall_score: Option<f32>,
+    /// Every `recall@N` column with its value.
+    pub recalls: Vec<(String, f32)>,
+    /// The time spent indexing in milliseconds, missing in the older result files.
+    pub indexing_time: Option<f64>,
+    /// The time spent searching in milliseconds, missing in the older result files.
+    pub search_time: Option<f64>,
+}
+
+impl ResultFile {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<ResultFile> {
+        let path = path.as_ref();
+        let manifest = Manifest::from_csv(path)?;
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("while reading {}", path.display()))?;
+
+        let mut lines = content.lines().filter(|l| !l.starts_with('#') && !l.trim().is_empty());
+        let header: Vec<_> = match lines.next() {
+            Some(header) => header.split(',').map(str::trim).collect(),
+            None => bail!("{} doesn't contain any header", path.display()),
+        };
+
+        // The files written by a single dataset run only name it in their manifest.
+        let dataset = match &manifest {
+            Some(manifest) if !header.contains(&"dataset") && manifest.datasets.len() == 1 => {
+                Some(manifest.datasets[0].name.clone())
+            }
+            _ => None,
+        };
+
+        let mut key_columns = Vec::new();
+        if dataset.is_some() {
+            key_columns.push("dataset".to_string());
+        }
+        key_columns.extend(header.iter().filter(|c| is_key_column(c)).map(|c| c.to_string()));
+
+        let mut rows: Vec<ResultRow> = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let values: Vec<_> = line.split(',').map(str::trim).collect();
+            let header = expand_db_sizes(&header, values.len());
+            if values.len() != header.len() {
+                bail!(
+                    "line {i} of {} contains {} values instead of {}",
+                    path.display(),
+                    values.len(),
+                    header.len()
+                );
+            }
+
+            let mut row = ResultRow::default();
+            if let Some(dataset) = &dataset {
+                row.key.push(("dataset".to_string(), dataset.clone()));
+            }
+            for (&column, &value) in header.iter().zip(&values) {
+                let context =
+                    || format!("while parsing `{column}` on line {i} of {}", path.display());
+                match column {
+                    // The size after every chunk, empty for the chunks that weren't indexed.
+                    column if column.starts_with("db size in bytes") => {
+                        if !value.is_empty() {
+                            row.db_size = Some(value.parse().with_context(context)?);
+                        }
+                    }
+                    "recall score" => row.recall_score = Some(value.parse().with_context(context)?),
+                    "indexing time in ms" => {
+                        row.indexing_time = Some(value.parse().with_context(context)?)
+                    }
+                    "search time in ms" => {
+                        row.search_time = Some(value.parse().with_context(context)?)
+                    }
+                    "contender version" => row.contender_version = Some(value.to_string()),
+                    column if column.starts_with("recall@") => {
+                        row.recalls.push((column.to_string(), value.parse().with_context(context)?))
+                    }
+                    column => row.key.push((column.to_string(), value.to_string())),
+                }
+            }
+            if rows.iter().any(|previous| previous.key == row.key) {
+                bail!(
+                    "line {i} of {} describes the same scenario as a previous line: {}",
+                    path.display(),
+                    format_key(&row.key)
+                );
+            }
+            rows.push(row);
+        }
+
+        Ok(ResultFile { manifest, key_columns, rows })
+    }
+}
+
+/// Whether the column identifies the scenario rather than measuring it.
+fn is_key_column(column: &str) -> bool {
+    !(column.starts_with("db size in bytes")
+        || column.starts_with("recall@")
+        || matches!(
+            column,
+            "recall score" | "indexing time in ms" | "search time in ms" | "contender version"
+        ))
+}
+
+/// The older result files have a single `db size in bytes` column for the size after every chunk,
+/// this column is repeated to match the number of `values` of a line.
+fn expand_db_sizes<'a>(header: &[&'a str], values: usize) -> Vec<&'a str> {
+    let extra = values.saturating_sub(header.len());
+    header
+        .iter()
+        .flat_map(|&column| {
+            let repeat = if column == "db size in bytes" { extra + 1 } else { 1 };
+            std::iter::repeat_n(column, repeat)
+        })
+        .collect()
+}
+
+/// The limits above which a difference between two result files is considered a regression.
+#[derive(Debug, Clone, Copy)]
+pub struct CompareThresholds {
+    /// The maximum absolute drop of any recall, e.g. `0.02`.
+    pub recall: f32,
+    /// The maximum relative increase of the database size, e.g. `0.1` for 10%.
+    pub size: Option<f64>,
+    /// The maximum relative increase of the indexing or search time, e.g. `0.5` for 50%.
+    pub time: Option<f64>,
+}
+
+/// Prints the differences between two result files, joined on their scenario key,
+/// and returns the number of regressions found.
+pub fn compare(old: &ResultFile, new: &ResultFile, thresholds: CompareThresholds) -> usize {
+    if let (Some(old), Some(new)) = (&old.manifest, &new.manifest) {
+        print_manifest_differences(old, new);
+    }
+
+    // The scenarios are joined on the columns both files have, e.g. a file
+    // written before the contenders were configurable has no `contender` column.
+    let shared: Vec<_> =
+        old.key_columns.iter().filter(|column| new.key_columns.contains(column)).collect();
+    for (columns, other, file) in
+        [(&old.key_columns, &new.key_columns, "old"), (&new.key_columns, &old.key_columns, "new")]
+    {
+        for column in columns.iter().filter(|column| !other.contains(column)) {
+            println!(
+                "\x1b[1;33mwarning\x1b[0m: the `{column}` column is only in the {file} results, \
+                 it is ignored to join the scenarios"
+            );
+        }
+    }
+
+    let mut regressions = 0;
+    for old_row in &old.rows {
+        let Some(new_row) =
+            new.rows.iter().find(|row| same_scenario(&row.key, &old_row.key, &shared))
+        else {
+            println!("{}: \x1b[1;33mmissing from the new results\x1b[0m", format_key(&old_row.key));
+            continue;
+        };
+
+        println!("{}", format_key(&old_row.key));
+        if let (Some(old), Some(new)) = (&old_row.contender_version, &new_row.contender_version) {
+            if old != new {
+                println!("  {:<20} {old} -> {new}", "contender version");
+            }
+        }
+
+        let old_recalls = old_row.recall_score.map(|r| ("recall score".to_string(), r));
+        let new_recalls = new_row.recall_score.map(|r| ("recall score".to_string(), r));
+        let old_recalls = old_recalls.iter().chain(&old_row.recalls);
+        let new_recalls = new_recalls.iter().chain(&new_row.recalls).collect::<Vec<_>>();
+        for (name, old) in old_recalls {
+            let Some((_, new)) = new_recalls.iter().find(|(n, _)| n == name) else { continue };
+            let delta = new - old;
+            let regression = recall_regressed(*old, *new, thresholds.recall);
+            regressions += regression as usize;
+            println!(
+                "  {name:<20} {:?} -> {:?} ({delta:+.2}){}",
+                Recall(*old),
+                Recall(*new),
+                regression_marker(regression)
+            );
+        }
+
+        if let (Some(old), Some(new)) = (old_row.db_size, new_row.db_size) {
+            let delta = relative_delta(old as f64, new as f64);
+            let regression = thresholds.size.is_some_and(|t| delta > t);
+            regressions += regression as usize;
+            println!(
+                "  {:<20} {:.2} -> {:.2} ({:+.2}%){}",
+                "db size",
+                Byte::from_u64(old).get_appropriate_unit(UnitType::Binary),
+                Byte::from_u64(new).get_appropriate_unit(UnitType::Binary),
+                delta * 100.0,
+                regression_marker(regression)
+            );
+        }
+
+        let times = [
+            ("indexing time", old_row.indexing_time, new_row.indexing_time),
+            ("search time", old_row.search_time, new_row.search_time),
+        ];
+        for (name, old, new) in times {
+            let (Some(old), Some(new)) = (old, new) else { continue };
+            let delta = relative_delta(old, new);
+            let regression = thresholds.time.is_some_and(|t| delta > t);
+            regressions += regression as usize;
+            println!(
+                "  {name:<20} {old:.2}ms -> {new:.2}ms ({:+.2}%){}",
+                delta * 100.0,
+                regression_marker(regression)
+            );
+        }
+    }
+
+    for new_row in &new.rows {
+        if !old.rows.iter().any(|row| same_scenario(&row.key, &new_row.key, &shared)) {
+            println!("{}: \x1b[1;33mmissing from the old results\x1b[0m", format_key(&new_row.key));
+        }
+    }
+
+    regressions
+}
+
+fn print_manifest_differences(old: &Manifest, new: &Manifest) {
+    if old.build.arroy != new.build.arroy {
+        println!("arroy: {} -> {}", old.build.arroy, new.build.arroy);
+    }
+    if old.build.arroy_0_5 != new.build.arroy_0_5 {
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("disabled"));
+        println!(
+            "arroy 0.5: {} -> {}",
+            version(&old.build.arroy_0_5),
+            version(&new.build.arroy_0_5)
+        );
+    }
+    if old.build.commit != new.build.commit {
+        println!("commit: {} -> {}", old.build.commit, new.build.commit);
+    }
+
+    let mut warnings = Vec::new();
+    if old.experiment.queries != new.experiment.queries {
+        warnings.push("the queries are not configured the same way");
+    }
+    if old.experiment.seeds != new.experiment.seeds {
+        warnings.push("the seeds are different");
+    }
+    if old.experiment.transforms != new.experiment.transforms {
+        warnings.push("the vectors were not transformed the same way");
+    }
+    let checksums =
+        |m: &Manifest| m.datasets.iter().map(|d| d.checksum.clone()).collect::<Vec<_>>();
+    if checksums(old) != checksums(new) {
+        warnings.push("the datasets are different");
+    }
+    if old.build.profile != new.build.profile || old.machine != new.machine {
+        warnings.push("the results were not produced on the same machine or with the same profile");
+    }
+    for warning in warnings {
+        println!("\x1b[1;33mwarning\x1b[0m: {warning}");
+    }
+    println!();
+}
+
+/// Whether the keys describe the same scenario, only the `shared` columns are compared.
+fn same_scenario(a: &[(String, String)], b: &[(String, String)], shared: &[&String]) -> bool {
+    let project = |key: &[(String, String)]| {
+        key.iter().filter(|(column, _)| shared.contains(&column)).cloned().collect::<Vec<_>>()
+    };
+    project(a) == project(b)
+}
+
+/// Whether the recall dropped by more than the threshold, compared at the
+/// precision the result files store them to not flag a drop of exactly the threshold.
+fn recall_regressed(old: f32, new: f32, threshold: f32) -> bool {
+    let hundredths = |recall: f32| (recall * 100.0).round() as i64;
+    hundredths(old) - hundredths(new) > hundredths(threshold)
+}
+
+fn format_key(key: &[(String, String)]) -> String {
+    key.iter().map(|(column, value)| format!("{column}: {value}")).collect::<Vec<_>>().join(", ")
+}
+
+fn relative_delta(old: f64, new: f64) -> f64 {
+    if old == 0.0 {
+        0.0
+    } else {
+        (new - old) / old
+    }
+}
+
+fn regression_marker(regression: bool) -> &'static str {
+    if regression {
+        " \x1b[1;31m<- regression\x1b[0m"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn result_file(content: &str) -> anyhow::Result<ResultFile> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        ResultFile::from_path(file.path())
+    }
+
+    #[test]
+    fn parse_old_result_file_with_repeated_db_sizes() {
+        let file = result_file(
+            "nb vectors,nb trees,db size in bytes,recall score,recall@1,recall@10\n\
+             1000,5,100,200,300,0.50,1.00,0.40\n\
+             1000,10,150,,,0.60,1.00,0.50\n\
+             \n\
+             2000,5,400,0.70,1.00,0.60\n",
+        )
+        .unwrap();
+        assert!(file.manifest.is_none());
+        assert_eq!(file.key_columns, ["nb vectors", "nb trees"]);
+        let sizes: Vec<_> = file.rows.iter().map(|row| row.db_size).collect();
+        assert_eq!(sizes, [Some(300), Some(150), Some(400)]);
+        let row = &file.rows[0];
+        assert_eq!(row.recall_score, Some(0.5));
+        assert_eq!(row.recalls, [("recall@1".to_string(), 1.0), ("recall@10".to_string(), 0.4)]);
+        assert_eq!(row.indexing_time, None);
+    }
+
+    #[test]
+    fn parse_result_file_with_chunk_columns() {
+        let file = result_file(
+            "filtering,nb vectors,nb trees,nb chunks,db size in bytes after chunk 1,\
+             db size in bytes after chunk 2,recall score,recall@1,indexing time in ms,search time in ms\n\
+             50%,1000,5,2,100,200,0.90,1.00,12.50,1.25\n",
+        )
+        .unwrap();
+        assert_eq!(file.key_columns, ["filtering", "nb vectors", "nb trees", "nb chunks"]);
+        let row = &file.rows[0];
+        assert_eq!(row.db_size, Some(200));
+        assert_eq!(row.indexing_time, Some(12.5));
+        assert_eq!(row.search_time, Some(1.25));
+    }
+
+    #[test]
+    fn parse_malformed_result_files() {
+        assert!(result_file("").is_err());
+        assert!(result_file("nb vectors,nb trees,recall score\n1000,5\n").is_err());
+        assert!(result_file("nb vectors,nb trees,recall score\n1000,5,high\n").is_err());
+        let duplicate = "nb vectors,nb trees,recall score\n1000,5,0.5\n1000,5,0.6\n";
+        assert!(result_file(duplicate).is_err());
+    }
+
+    #[test]
+    fn join_on_the_shared_key_columns() {
+        let key = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(c, v)| (c.to_string(), v.to_string())).collect()
+        };
+        let old = key(&[("nb vectors", "1000"), ("nb trees", "5")]);
+        let new = key(&[("contender", "arroy"), ("nb vectors", "1000"), ("nb trees", "5")]);
+        let (vectors, trees) = ("nb vectors".to_string(), "nb trees".to_string());
+        assert!(same_scenario(&old, &new, &[&vectors, &trees]));
+        let other = key(&[("contender", "arroy"), ("nb vectors", "2000"), ("nb trees", "5")]);
+        assert!(!same_scenario(&old, &other, &[&vectors, &trees]));
+    }
+
+    #[test]
+    fn recall_drop_of_exactly_the_threshold_is_not_a_regression() {
+        assert!(!recall_regressed(0.97, 0.95, 0.02));
+        assert!(!recall_regressed(0.50, 0.48, 0.02));
+        assert!(recall_regressed(0.97, 0.94, 0.02));
+        assert!(!recall_regressed(0.95, 0.97, 0.02));
+        assert!(!recall_regressed(0.95, 0.95, 0.0));
+        assert!(recall_regressed(0.95, 0.94, 0.0));
+    }
+}
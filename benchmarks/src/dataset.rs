@@ -1,28 +1,164 @@
+use std::borrow::Cow;
+use std::fmt;
 use std::fs::File;
 use std::marker::PhantomData;
 use std::mem;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-use anyhow::Context;
+use anyhow::{bail, ensure, Context};
 use bytemuck::{AnyBitPattern, PodCastError};
+use half::{bf16, f16};
+use matfile::{Dtype, MatHeader};
 use memmap2::Mmap;
+use rayon::iter::ParallelIterator;
+use rayon::slice::ParallelSlice;
+
+use crate::{BvecsView, FvecsView, NpyView, ShardedDataset, Transform};
+
+/// The types of values a `.mat` file can store.
+pub trait MatValue: AnyBitPattern + fmt::Debug + Send + Sync {
+    const DTYPE: Dtype;
+
+    /// Converts a vector to `f32`, without copying it when it is already made of `f32`.
+    fn to_f32_vector(vector: &[Self]) -> Cow<'_, [f32]>;
+}
+
+impl MatValue for f32 {
+    const DTYPE: Dtype = Dtype::F32;
+
+    fn to_f32_vector(vector: &[f32]) -> Cow<'_, [f32]> {
+        Cow::Borrowed(vector)
+    }
+}
+
+impl MatValue for f16 {
+    const DTYPE: Dtype = Dtype::F16;
+
+    fn to_f32_vector(vector: &[f16]) -> Cow<'_, [f32]> {
+        Cow::Owned(vector.iter().map(|x| x.to_f32()).collect())
+    }
+}
+
+impl MatValue for bf16 {
+    const DTYPE: Dtype = Dtype::BF16;
+
+    fn to_f32_vector(vector: &[bf16]) -> Cow<'_, [f32]> {
+        Cow::Owned(vector.iter().map(|x| x.to_f32()).collect())
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MatLEView<T> {
-    name: &'static str,
+    name: String,
+    path: PathBuf,
     mmap: Arc<Mmap>,
+    /// The offset of the first vector, right after the header.
+    offset: usize,
     dimensions: usize,
+    len: usize,
+    /// The CRC32 of the whole file, known from the open when the header records the one of the vectors.
+    checksum: OnceLock<u32>,
     _marker: PhantomData<T>,
 }
 
-impl<T: AnyBitPattern> MatLEView<T> {
-    pub fn new(name: &'static str, path: &str, dimensions: usize) -> MatLEView<T> {
-        let file = File::open(path).with_context(|| format!("while opening {path}")).unwrap();
-        let mmap = unsafe { Mmap::map(&file).unwrap() };
+impl<T: MatValue> MatLEView<T> {
+    /// Opens a `.mat` file whose number of dimensions is known beforehand.
+    ///
+    /// The number of dimensions is checked against the header of the file if there is one.
+    pub fn new(name: &str, path: &str, dimensions: usize) -> MatLEView<T> {
+        MatLEView::open(path, Some(dimensions)).unwrap().with_name(name)
+    }
 
-        assert!(mmap.len() != 0, "The file is empty");
-        assert!((mmap.len() / mem::size_of::<T>()) % dimensions == 0);
-        MatLEView { name, mmap: Arc::new(mmap), dimensions, _marker: PhantomData }
+    /// Opens a `.mat` file and reads its name and number of dimensions from its header.
+    ///
+    /// The legacy files without header can only be opened if the `dimensions` are specified.
+    /// When the header records the checksum of the vectors, they are read entirely to verify it
+    /// and the checksum of the whole file is derived from it.
+    pub fn open(path: impl AsRef<Path>, dimensions: Option<usize>) -> anyhow::Result<MatLEView<T>> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let tsize = mem::size_of::<T>();
+
+        let header = MatHeader::decode(&mmap)
+            .with_context(|| format!("while reading the header of {}", path.display()))?;
+        let file_checksum = OnceLock::new();
+        let (name, offset, dimensions, len) = match header {
+            Some(header) => {
+                if let Some(dimensions) = dimensions {
+                    ensure!(
+                        header.dimensions == dimensions,
+                        "{} contains vectors of {} dimensions but {dimensions} were expected",
+                        path.display(),
+                        header.dimensions,
+                    );
+                }
+                ensure!(
+                    header.dtype == T::DTYPE,
+                    "{} contains {} values that cannot be read as {}",
+                    path.display(),
+                    header.dtype,
+                    T::DTYPE,
+                );
+                let expected = header.size() + header.data_len();
+                ensure!(
+                    mmap.len() == expected,
+                    "{} is {} bytes long but its header declares {} vectors, i.e. {expected} bytes",
+                    path.display(),
+                    mmap.len(),
+                    header.rows,
+                );
+                if let Some(expected) = header.checksum {
+                    let actual = checksum(&mmap[header.size()..]);
+                    ensure!(
+                        actual == expected,
+                        "{} is corrupted, the CRC32 of its vectors is {actual:08x} but its header declares {expected:08x}",
+                        path.display(),
+                    );
+                    let mut hasher = crc32fast::Hasher::new();
+                    hasher.update(&mmap[..header.size()]);
+                    let data_len = (mmap.len() - header.size()) as u64;
+                    hasher.combine(&crc32fast::Hasher::new_with_initial_len(actual, data_len));
+                    let _ = file_checksum.set(hasher.finalize());
+                }
+                (header.name.clone(), header.size(), header.dimensions, header.rows)
+            }
+            None => {
+                let Some(dimensions) = dimensions else {
+                    bail!(
+                        "{} doesn't have a header, its number of dimensions must be specified",
+                        path.display()
+                    )
+                };
+                ensure!(!mmap.is_empty(), "{} is empty", path.display());
+                ensure!(
+                    (mmap.len() / tsize).is_multiple_of(dimensions),
+                    "{} cannot contain vectors of {dimensions} dimensions",
+                    path.display()
+                );
+                let name =
+                    path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into());
+                (name, 0, dimensions, mmap.len() / tsize / dimensions)
+            }
+        };
+
+        Ok(MatLEView {
+            name,
+            path: path.to_path_buf(),
+            mmap: Arc::new(mmap),
+            offset,
+            dimensions,
+            len,
+            checksum: file_checksum,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Replaces the name read from the header.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
     }
 
     pub fn header(&self) {
@@ -34,27 +170,42 @@ impl<T: AnyBitPattern> MatLEView<T> {
         );
     }
 
-    pub fn name(&self) -> &'static str {
-        self.name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     pub fn dimensions(&self) -> usize {
         self.dimensions
     }
 
+    /// The size of the file in bytes.
+    pub fn file_size(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// The CRC32 of the whole file, computed in parallel the first time
+    /// unless it was derived from the checksum of the header.
+    pub fn checksum(&self) -> u32 {
+        *self.checksum.get_or_init(|| checksum(&self.mmap))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
     pub fn len(&self) -> usize {
-        ((self.mmap.len() / mem::size_of::<T>()) / self.dimensions) - 1
+        self.len
     }
 
     pub fn get(&self, index: usize) -> Option<Result<&[T], PodCastError>> {
         let tsize = mem::size_of::<T>();
-        if (index * self.dimensions + self.dimensions) * tsize < self.mmap.len() {
-            let start = index * self.dimensions;
-            let bytes = &self.mmap[start * tsize..(start + self.dimensions) * tsize];
+        if index < self.len {
+            let start = self.offset + index * self.dimensions * tsize;
+            let bytes = &self.mmap[start..start + self.dimensions * tsize];
             match bytemuck::try_cast_slice::<u8, T>(bytes) {
                 Ok(slice) => Some(Ok(slice)),
                 Err(e) => Some(Err(e)),
@@ -75,7 +226,7 @@ impl<T: AnyBitPattern> MatLEView<T> {
 
 impl<T> PartialEq for MatLEView<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.name.eq(other.name)
+        self.name.eq(&other.name)
     }
 }
 
@@ -89,14 +240,136 @@ impl<T> PartialOrd for MatLEView<T> {
 
 impl<T> Ord for MatLEView<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.name.cmp(other.name)
+        self.name.cmp(&other.name)
     }
 }
 
+/// A matrix of vectors that can be benchmarked, whatever its format on disk.
+pub trait VectorDataset: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+    /// The file the vectors are read from.
+    fn path(&self) -> &Path;
+    fn dimensions(&self) -> usize;
+    /// The number of dimensions of the vectors stored in the file, before they are truncated or transformed.
+    fn file_dimensions(&self) -> usize {
+        self.dimensions()
+    }
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The size of the file in bytes.
+    fn file_size(&self) -> usize;
+    /// The CRC32 of the whole file.
+    fn checksum(&self) -> u32;
+    /// Returns the vector at `index`, converted to `f32` if it is stored in another type.
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>>;
+    /// The transforms applied to the vectors of the file.
+    fn transforms(&self) -> &[Transform] {
+        &[]
+    }
+}
+
+impl<T: MatValue> VectorDataset for MatLEView<T> {
+    fn name(&self) -> &str {
+        self.name()
+    }
+
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.file_size()
+    }
+
+    fn checksum(&self) -> u32 {
+        self.checksum()
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        self.get(index).map(|vector| T::to_f32_vector(vector.unwrap()))
+    }
+}
+
+impl From<MatLEView<f32>> for Arc<dyn VectorDataset> {
+    fn from(dataset: MatLEView<f32>) -> Self {
+        Arc::new(dataset)
+    }
+}
+
+/// Opens a dataset file according to its extension, `.fvecs` and `.bvecs` for the
+/// TEXMEX formats, `.npy` and `.npz` for the NumPy ones, `.shards.toml` for the manifests
+/// of the shards written by `parquet2mat --shard-size` and `.mat` for everything else.
+///
+/// The `dimensions` are only required by the `.mat` files without header,
+/// these files are always read as `f32`.
+pub fn open_dataset(
+    path: impl AsRef<Path>,
+    name: Option<&str>,
+    dimensions: Option<usize>,
+) -> anyhow::Result<Arc<dyn VectorDataset>> {
+    macro_rules! named {
+        ($dataset:expr) => {
+            match name {
+                Some(name) => Arc::new($dataset.with_name(name)) as Arc<dyn VectorDataset>,
+                None => Arc::new($dataset),
+            }
+        };
+    }
+
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    Ok(match extension {
+        "fvecs" => named!(FvecsView::open(path, dimensions)?),
+        "bvecs" => named!(BvecsView::open(path, dimensions)?),
+        "npy" => named!(NpyView::open(path, dimensions)?),
+        "npz" => named!(NpyView::open_npz(path, None, dimensions)?),
+        "toml" => named!(ShardedDataset::open(path, dimensions)?),
+        _ => {
+            let file =
+                File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+            match MatHeader::read_from(file)?.map_or(Dtype::F32, |header| header.dtype) {
+                Dtype::F32 => named!(MatLEView::<f32>::open(path, dimensions)?),
+                Dtype::F16 => named!(MatLEView::<f16>::open(path, dimensions)?),
+                Dtype::BF16 => named!(MatLEView::<bf16>::open(path, dimensions)?),
+            }
+        }
+    })
+}
+
+/// Computes the CRC32 of a file, in parallel.
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    const CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+    bytes
+        .par_chunks(CHUNK_SIZE)
+        .map(|chunk| {
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(chunk);
+            hasher
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .fold(crc32fast::Hasher::new(), |mut hasher, chunk| {
+            hasher.combine(&chunk);
+            hasher
+        })
+        .finalize()
+}
+
 pub const DATACOMP_SMALL: &str = "assets/datacomp-small.mat";
 pub const DATACOMP_SMALL_DIMENSIONS: usize = 768;
 pub fn datacomp_small() -> MatLEView<f32> {
-    MatLEView::new("Hackernews top posts", DATACOMP_SMALL, DATACOMP_SMALL_DIMENSIONS)
+    MatLEView::new("Datacomp small", DATACOMP_SMALL, DATACOMP_SMALL_DIMENSIONS)
 }
 
 pub const HN_TOP_POSTS_PATH: &str = "assets/hn-top-posts.mat";
@@ -119,8 +392,55 @@ pub const DB_PEDIA_OPENAI_TEXT_EMBEDDING_3_LARGE_DIMENSIONS: usize = 3072;
 // }
 
 pub const DB_PEDIA_OPENAI_TEXT_EMBEDDING_ADA_002_PATH: &str =
-    "assets/db-pedia-OpenAI-text-embedding-3-large.mat";
+    "assets/db-pedia-OpenAI-text-embedding-ada-002.mat";
 pub const DB_PEDIA_OPENAI_TEXT_EMBEDDING_ADA_002_DIMENSIONS: usize = 1536;
 // pub fn hn_posts() -> MatLEView<f32> {
 //     MatLEView::new("Hackernews posts", "assets/hn-posts.mat", 512)
 // }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::*;
+
+    fn mat_file(header: &MatHeader, vectors: &[f32]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        header.write_into(&mut file).unwrap();
+        file.write_all(bytemuck::cast_slice(vectors)).unwrap();
+        file
+    }
+
+    #[test]
+    fn open_verifies_the_checksum_of_the_vectors() {
+        let vectors: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        let crc = checksum(bytemuck::cast_slice(&vectors));
+        let header = MatHeader::new("test", Dtype::F32, 3, 4).with_checksum(crc);
+        let file = mat_file(&header, &vectors);
+
+        let view = MatLEView::<f32>::open(file.path(), None).unwrap();
+        assert_eq!(view.len(), 4);
+        assert_eq!(view.get(1).unwrap().unwrap(), [3.0, 4.0, 5.0]);
+        // The checksum derived from the header is the one of the whole file.
+        assert_eq!(view.checksum(), checksum(&fs::read(file.path()).unwrap()));
+
+        let header = MatHeader::new("test", Dtype::F32, 3, 4).with_checksum(crc ^ 1);
+        let file = mat_file(&header, &vectors);
+        let error = MatLEView::<f32>::open(file.path(), None).unwrap_err();
+        assert!(error.to_string().contains("is corrupted"), "{error}");
+    }
+
+    #[test]
+    fn open_checks_the_header() {
+        let vectors = [0.0; 6];
+        let file = mat_file(&MatHeader::new("test", Dtype::F32, 3, 2), &vectors);
+        let view = MatLEView::<f32>::open(file.path(), None).unwrap();
+        assert_eq!(view.checksum(), checksum(&fs::read(file.path()).unwrap()));
+        assert!(MatLEView::<f32>::open(file.path(), Some(2)).is_err());
+        assert!(MatLEView::<f16>::open(file.path(), None).is_err());
+
+        let file = mat_file(&MatHeader::new("test", Dtype::F32, 3, 3), &vectors);
+        assert!(MatLEView::<f32>::open(file.path(), None).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::ensure;
+use ordered_float::OrderedFloat;
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{VectorDataset, RNG_SEED};
+
+/// How a dataset is sampled and characterised by [`DatasetDescription::new`].
+#[derive(Debug, Clone, Copy)]
+pub struct DescribeOptions {
+    /// The number of vectors randomly picked, every estimation is done on this sample only.
+    pub sample: usize,
+    /// The number of neighbours used by the estimations.
+    pub k: usize,
+    /// The number of clusters searched by the k-means.
+    pub clusters: usize,
+    pub seed: u64,
+}
+
+impl Default for DescribeOptions {
+    fn default() -> Self {
+        DescribeOptions { sample: 5000, k: 20, clusters: 16, seed: RNG_SEED }
+    }
+}
+
+/// The properties of a dataset that may influence the number of trees it requires.
+///
+/// The distances are the euclidean distances between the normalized vectors,
+/// they rank the neighbours the same way the cosine does.
+#[derive(Debug, Clone)]
+pub struct DatasetDescription {
+    pub name: String,
+    /// The CRC32 of the file, the same as the one of the manifest of the result files.
+    pub checksum: String,
+    pub dimensions: usize,
+    pub rows: usize,
+    pub options: DescribeOptions,
+    /// The number of vectors of the sample that were ignored because their norm is zero
+    /// or they are a duplicate of another vector of the sample.
+    pub ignored: usize,
+    /// The local intrinsic dimensionality estimated with the maximum likelihood
+    /// estimator of Levina and Bickel, averaged as proposed by MacKay and Ghahramani.
+    pub lid_mle: f64,
+    /// The intrinsic dimensionality estimated from the ratio of the distances
+    /// to the two nearest neighbours, see Facco et al.
+    pub lid_twonn: f64,
+    /// The skewness of the k-occurrence distribution, the higher the more some
+    /// vectors are hubs appearing in the neighbours of many others.
+    pub hubness: f64,
+    /// The ratio of vectors that are never among the k nearest neighbours of another vector.
+    pub antihubs: f64,
+    /// The average ratio between the mean distance to the other vectors and the distance
+    /// to the nearest neighbour. Close to 1 when the nearest neighbours are meaningless.
+    pub relative_contrast: f64,
+    /// The size of the largest cluster divided by the average size of a cluster.
+    pub cluster_imbalance: f64,
+    /// The ratio of the k nearest neighbours belonging to the same cluster as the vector.
+    pub cluster_agreement: f64,
+}
+
+impl DatasetDescription {
+    /// Estimates the properties of the dataset on a sample, it requires computing
+    /// the distances between every pair of vectors of the sample.
+    pub fn new(
+        dataset: &dyn VectorDataset,
+        options: DescribeOptions,
+    ) -> anyhow::Result<DatasetDescription> {
+        let DescribeOptions { sample, k, clusters, seed } = options;
+        ensure!(k >= 2, "At least two neighbours are required to estimate the dimensionality");
+        ensure!(clusters >= 1, "At least one cluster is required");
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut indices =
+            index::sample(&mut rng, dataset.len(), sample.min(dataset.len())).into_vec();
+        indices.sort_unstable();
+
+        let mut ignored = 0;
+        let mut vectors: Vec<Vec<f32>> = Vec::with_capacity(indices.len());
+        for i in indices {
+            let vector = dataset.vector(i).unwrap();
+            let norm = dot(&vector, &vector).sqrt();
+            if norm == 0.0 || !norm.is_finite() {
+                ignored += 1;
+            } else {
+                vectors.push(vector.iter().map(|x| x / norm).collect());
+            }
+        }
+        vectors.sort_unstable_by(|a, b| {
+            a.iter().map(|x| OrderedFloat(*x)).cmp(b.iter().map(|x| OrderedFloat(*x)))
+        });
+        let len = vectors.len();
+        vectors.dedup();
+        ignored += len - vectors.len();
+        ensure!(
+            vectors.len() > k,
+            "The sample only contains {} usable vectors, at least {} are required",
+            vectors.len(),
+            k + 1
+        );
+
+        let neighbours = nearest_neighbours(&vectors, k);
+        let n = neighbours.len() as f64;
+        // The distance estimators are undefined for the vectors too close to their nearest neighbour.
+        let distinct: Vec<_> = neighbours.iter().filter(|(nns, _)| nns[0].1 > 0.0).collect();
+        let distinct_n = distinct.len() as f64;
+
+        let lid_mle = {
+            let inverse: f64 = distinct
+                .iter()
+                .map(|(nns, _)| {
+                    let tk = nns[k - 1].1 as f64;
+                    nns[..k - 1].iter().map(|(_, tj)| (tk / *tj as f64).ln()).sum::<f64>()
+                        / (k - 1) as f64
+                })
+                .sum::<f64>()
+                / distinct_n;
+            1.0 / inverse
+        };
+        let lid_twonn = distinct_n
+            / distinct.iter().map(|(nns, _)| (nns[1].1 as f64 / nns[0].1 as f64).ln()).sum::<f64>();
+
+        let occurrences: Vec<_> = (0..vectors.len()).map(|_| AtomicUsize::new(0)).collect();
+        neighbours.par_iter().flat_map(|(nns, _)| nns).for_each(|(id, _)| {
+            occurrences[*id].fetch_add(1, Ordering::Relaxed);
+        });
+        let occurrences: Vec<_> = occurrences.into_iter().map(|o| o.into_inner() as f64).collect();
+        // The mean of the k-occurrences is always k.
+        let variance = occurrences.iter().map(|o| (o - k as f64).powi(2)).sum::<f64>() / n;
+        let hubness = occurrences.iter().map(|o| (o - k as f64).powi(3)).sum::<f64>()
+            / n
+            / variance.powf(1.5);
+        let antihubs = occurrences.iter().filter(|&&o| o == 0.0).count() as f64 / n;
+
+        let relative_contrast =
+            distinct.iter().map(|(nns, mean)| *mean as f64 / nns[0].1 as f64).sum::<f64>()
+                / distinct_n;
+
+        let assignments = kmeans(&vectors, clusters.min(vectors.len()), &mut rng);
+        let mut sizes = vec![0usize; clusters];
+        assignments.iter().for_each(|&c| sizes[c] += 1);
+        let cluster_imbalance =
+            *sizes.iter().max().unwrap() as f64 / (n / clusters.min(vectors.len()) as f64);
+        let cluster_agreement = neighbours
+            .iter()
+            .enumerate()
+            .map(|(i, (nns, _))| {
+                nns.iter().filter(|(j, _)| assignments[*j] == assignments[i]).count()
+            })
+            .sum::<usize>() as f64
+            / (n * k as f64);
+
+        Ok(DatasetDescription {
+            name: dataset.name().to_string(),
+            checksum: format!("{:08x}", dataset.checksum()),
+            dimensions: dataset.dimensions(),
+            rows: dataset.len(),
+            options,
+            ignored,
+            lid_mle,
+            lid_twonn,
+            hubness,
+            antihubs,
+            relative_contrast,
+            cluster_imbalance,
+            cluster_agreement,
+        })
+    }
+
+    /// The CSV header matching the lines generated by [`Self::csv_line`].
+    ///
+    /// The `dataset` and `checksum` columns can be joined with the datasets of the manifest
+    /// at the top of the result files.
+    pub fn csv_header() -> &'static str {
+        "dataset,checksum,dimensions,nb vectors,sample,k,seed,ignored,lid mle,lid twonn,hubness,antihubs,relative contrast,clusters,cluster imbalance,cluster agreement"
+    }
+
+    pub fn csv_line(&self) -> String {
+        let DescribeOptions { sample, k, clusters, seed } = self.options;
+        format!(
+            "{},{},{},{},{},{k},{seed},{},{:.2},{:.2},{:.3},{:.3},{:.3},{clusters},{:.2},{:.3}",
+            self.name.replace(',', " "),
+            self.checksum,
+            self.dimensions,
+            self.rows,
+            sample.min(self.rows),
+            self.ignored,
+            self.lid_mle,
+            self.lid_twonn,
+            self.hubness,
+            self.antihubs,
+            self.relative_contrast,
+            self.cluster_imbalance,
+            self.cluster_agreement,
+        )
+    }
+}
+
+fn dot(left: &[f32], right: &[f32]) -> f32 {
+    left.iter().zip(right).map(|(l, r)| l * r).sum()
+}
+
+/// The euclidean distance between two normalized vectors.
+fn normalized_distance(left: &[f32], right: &[f32]) -> f32 {
+    (2.0 - 2.0 * dot(left, right)).max(0.0).sqrt()
+}
+
+/// Returns, for every vector, its `k` nearest neighbours sorted by distance
+/// and its mean distance to all the other vectors.
+fn nearest_neighbours(vectors: &[Vec<f32>], k: usize) -> Vec<(Vec<(usize, f32)>, f32)> {
+    vectors
+        .par_iter()
+        .enumerate()
+        .map(|(i, vector)| {
+            let mut distances: Vec<_> = vectors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(j, other)| (j, normalized_distance(vector, other)))
+                .collect();
+            let mean = distances.iter().map(|(_, d)| d).sum::<f32>() / distances.len() as f32;
+            distances.select_nth_unstable_by_key(k - 1, |(_, d)| OrderedFloat(*d));
+            distances.truncate(k);
+            distances.sort_unstable_by_key(|(_, d)| OrderedFloat(*d));
+            (distances, mean)
+        })
+        .collect()
+}
+
+/// A spherical k-means, returns the cluster of every vector.
+fn kmeans(vectors: &[Vec<f32>], clusters: usize, rng: &mut StdRng) -> Vec<usize> {
+    const ITERATIONS: usize = 25;
+
+    let mut centroids: Vec<Vec<f32>> =
+        index::sample(rng, vectors.len(), clusters).iter().map(|i| vectors[i].clone()).collect();
+    let mut assignments = vec![usize::MAX; vectors.len()];
+
+    for _ in 0..ITERATIONS {
+        let new_assignments: Vec<_> = vectors
+            .par_iter()
+            .map(|vector| {
+                (0..clusters).max_by_key(|&c| OrderedFloat(dot(vector, &centroids[c]))).unwrap()
+            })
+            .collect();
+        if new_assignments == assignments {
+            break;
+        }
+        assignments = new_assignments;
+
+        let dimensions = vectors[0].len();
+        let mut sums = vec![vec![0.0; dimensions]; clusters];
+        for (vector, &c) in vectors.iter().zip(&assignments) {
+            sums[c].iter_mut().zip(vector).for_each(|(s, x)| *s += x);
+        }
+        for (centroid, sum) in centroids.iter_mut().zip(sums) {
+            let norm = dot(&sum, &sum).sqrt();
+            // An empty cluster keeps its previous centroid.
+            if norm != 0.0 {
+                *centroid = sum.iter().map(|x| x / norm).collect();
+            }
+        }
+    }
+
+    assignments
+}
@@ -0,0 +1,207 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use byte_unit::Byte;
+use serde::{Deserialize, Serialize};
+
+use crate::scenarios::*;
+use crate::{
+    open_dataset, transform_datasets, truncate_datasets, AttributeFilter, BenchmarkPlan,
+    DatasetRegistry, DatasetSource, GroundTruthFile, QuerySpec, Transform, VectorDataset,
+    ARROY_SEED, RNG_SEED,
+};
+
+/// An experiment declared in a TOML file.
+///
+/// It describes everything required to reproduce a result file and is written,
+/// once resolved, in the [`Manifest`](crate::Manifest) at the top of the CSV it produced.
+///
+/// ```toml
+/// datasets = ["db-pedia3-large"]
+/// count = [512, 1024, 2048, 4096]
+/// nb-trees = [1, 8, 32, 64]
+/// output = "results/3072_dims.csv"
+///
+/// [queries]
+/// nb-queries = 100
+/// recall-tested = [1, 10, 20, 50, 100, 500]
+///
+/// [seeds]
+/// queries = 38
+/// arroy = 13
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Experiment {
+    /// The datasets to run, all the built-in ones are ran if empty.
+    ///
+    /// A dataset is either the name of a built-in dataset, the name of a dataset of the
+    /// [`DatasetRegistry`], a file, e.g. `{ name = "movies", path = "assets/movies.mat" }`,
+    /// or a [`SyntheticSpec`](crate::SyntheticSpec), e.g. `{ synthetic = "gaussian", dimensions = 4096, count = 100000 }`.
+    #[serde(default)]
+    pub datasets: Vec<DatasetSource>,
+    /// The number of dimensions of the dataset files generated without header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    /// The transforms applied, in order, to the vectors before indexing them,
+    /// e.g. `["center", "pca:256"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<Transform>,
+    /// Writes the transformed vectors into a `.mat` file next to the dataset
+    /// and reads them from it the next times.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cache_transforms: bool,
+    /// The number of dimensions the vectors are truncated to before being renormalized,
+    /// every dataset is benchmarked once per number of dimensions. Meant for the Matryoshka
+    /// embeddings, like the OpenAI `text-embedding-3` ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncate_dims: Vec<usize>,
+    /// The different number of documents to evaluate from the dataset.
+    pub count: Vec<usize>,
+    /// The different number of trees to generate for each number of documents.
+    pub nb_trees: Vec<usize>,
+    #[serde(default = "default_contenders")]
+    pub contenders: Vec<ScenarioContender>,
+    #[serde(default = "default_distances")]
+    pub distances: Vec<ScenarioDistance>,
+    #[serde(default = "default_over_samplings")]
+    pub over_samplings: Vec<ScenarioOversampling>,
+    #[serde(default = "default_filterings")]
+    pub filterings: Vec<ScenarioFiltering>,
+    /// The filter selecting the candidates of the `attributes` filtering, e.g. `"lang = en AND year >= 2020"`.
+    /// It is applied to the `.attributes.jsonl` file next to every dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribute_filter: Option<AttributeFilter>,
+    #[serde(default = "default_number_of_chunks")]
+    pub number_of_chunks: Vec<usize>,
+    /// The time to sleep between each chunk indexing specified in seconds.
+    #[serde(default)]
+    pub sleep_between_chunks: usize,
+    /// Memory available for indexing, unlimited if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Byte>,
+    /// Memory available to compute the exact nearest neighbours of the queries, unlimited
+    /// if not specified. The vectors are read in blocks fitting in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ground_truth_memory: Option<Byte>,
+    /// The number of threads to use for indexing, all of them if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub queries: QuerySpec,
+    #[serde(default)]
+    pub seeds: Seeds,
+    /// The CSV file to write the results to, the standard output if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Seeds {
+    /// The seed used to pick the queries.
+    pub queries: u64,
+    /// The seed used by arroy to generate the trees.
+    pub arroy: u64,
+}
+
+impl Default for Seeds {
+    fn default() -> Self {
+        Seeds { queries: RNG_SEED, arroy: ARROY_SEED }
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn default_contenders() -> Vec<ScenarioContender> {
+    vec![ScenarioContender::Arroy]
+}
+
+fn default_distances() -> Vec<ScenarioDistance> {
+    vec![ScenarioDistance::Cosine]
+}
+
+fn default_over_samplings() -> Vec<ScenarioOversampling> {
+    vec![ScenarioOversampling::X1]
+}
+
+fn default_filterings() -> Vec<ScenarioFiltering> {
+    vec![ScenarioFiltering::NoFilter]
+}
+
+fn default_number_of_chunks() -> Vec<usize> {
+    vec![1]
+}
+
+impl Experiment {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Experiment> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("while reading {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("while parsing {}", path.display()))
+    }
+
+    /// Replaces the implicit values by the ones that will really be used
+    /// and the registered datasets by their files.
+    pub fn resolve(mut self, registry: &DatasetRegistry) -> anyhow::Result<Experiment> {
+        if self.datasets.is_empty() {
+            self.datasets = enum_iterator::all::<Dataset>().map(DatasetSource::from).collect();
+        }
+        self.datasets = self
+            .datasets
+            .into_iter()
+            .map(|dataset| dataset.resolve(registry))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(self)
+    }
+
+    pub fn open_datasets(&self) -> anyhow::Result<Vec<Arc<dyn VectorDataset>>> {
+        let datasets = self
+            .datasets
+            .iter()
+            .map(|dataset| dataset.open(self.dimensions))
+            .collect::<anyhow::Result<_>>()?;
+        let datasets = transform_datasets(datasets, &self.transforms, self.cache_transforms)?;
+        truncate_datasets(datasets, &self.truncate_dims)
+    }
+
+    pub fn to_plan(&self) -> anyhow::Result<BenchmarkPlan> {
+        let plan = BenchmarkPlan::new()
+            .datasets(self.open_datasets()?)
+            .counts(self.count.iter().copied())
+            .nb_trees(self.nb_trees.iter().copied())
+            .contenders(self.contenders.iter().copied())
+            .distances(self.distances.iter().copied())
+            .over_samplings(self.over_samplings.iter().copied())
+            .filterings(self.filterings.iter().copied())
+            .number_of_chunks(self.number_of_chunks.iter().copied())
+            .queries(self.queries.clone())
+            .rng_seed(self.seeds.queries)
+            .arroy_seed(self.seeds.arroy)
+            .sleep_between_chunks(self.sleep_between_chunks)
+            .memory(self.memory.map_or(usize::MAX, |m| m.as_u64() as usize))
+            .ground_truth_memory(
+                self.ground_truth_memory.map_or(usize::MAX, |m| m.as_u64() as usize),
+            );
+        let mut plan = match &self.attribute_filter {
+            Some(filter) => plan.attribute_filter(filter.clone()),
+            None => plan,
+        };
+        if let Some(path) = &self.queries.path {
+            plan = plan.query_vectors(open_dataset(path, None, self.dimensions)?);
+        }
+        for path in &self.queries.ground_truth {
+            plan = plan.ground_truth(GroundTruthFile::open(path)?);
+        }
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+}
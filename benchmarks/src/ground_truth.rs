@@ -0,0 +1,299 @@
+//! The exact nearest neighbours of the queries, exported so that other tools can be
+//! evaluated against the answers the benchmarks expect.
+//!
+//! [`GroundTruth::write_into`] creates one directory per dataset and count:
+//!
+//! ```text
+//! <dataset>-<count>/
+//! ├── queries.fvecs       the query vectors
+//! ├── neighbours.ivecs    the ids of their nearest neighbours, from the nearest to the farthest
+//! ├── distances.fvecs     the distances to these neighbours
+//! └── ann/                the same arrays named like in the HDF5 files of ann-benchmarks
+//!     ├── test.npy        f32, (queries, dimensions)
+//!     ├── neighbors.npy   i32, (queries, neighbours)
+//!     ├── distances.npy   f32, (queries, neighbours)
+//!     └── attrs.json      the attributes of the HDF5 file, `distance`, `dimension`...
+//! ```
+//!
+//! The `train` vectors are not copied, they are the `count` first vectors of the dataset.
+//! The ids are the indexes of the vectors in the dataset and the queries are picked among them,
+//! unless they were imported.
+//!
+//! These directories, the `.ivecs` files and the `.npy` arrays of `i32` shipped with the
+//! public datasets can be read back with [`GroundTruthFile::open`].
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use matfile::{NpyDtype, NpyHeader};
+use serde::{Deserialize, Serialize};
+
+use crate::scenarios::ScenarioDistance;
+use crate::{write_vecs, IvecsView, VectorDataset};
+
+/// The nearest neighbours of the queries among the `count` first vectors of a dataset.
+#[derive(Debug, Clone)]
+pub struct GroundTruth {
+    pub dataset: String,
+    pub distance: ScenarioDistance,
+    pub dimensions: usize,
+    /// The number of vectors among which the neighbours were searched.
+    pub count: usize,
+    /// The checksum of the dataset file.
+    pub checksum: u32,
+    /// The seed used to pick the queries.
+    pub seed: u64,
+    /// The id of every query in the dataset, empty if the queries were imported.
+    pub query_ids: Vec<u32>,
+    pub queries: Vec<Vec<f32>>,
+    /// The ids of the nearest neighbours of every query, from the nearest to the farthest.
+    pub neighbours: Vec<Vec<u32>>,
+    /// The distance between every query and its neighbours.
+    pub distances: Vec<Vec<f32>>,
+}
+
+/// The attributes of an ann-benchmarks HDF5 file, with what's required to recompute the ground truth.
+#[derive(Serialize, Deserialize)]
+struct Attributes {
+    distance: String,
+    dimension: usize,
+    point_type: String,
+    dataset: String,
+    count: usize,
+    checksum: u32,
+    seed: u64,
+    query_ids: Vec<u32>,
+}
+
+impl GroundTruth {
+    /// The name of the directory written by [`Self::write_into`], e.g. `hackernews-posts-10000`.
+    pub fn directory_name(&self) -> String {
+        let mut name = String::new();
+        for c in self.dataset.chars() {
+            if c.is_ascii_alphanumeric() {
+                name.push(c.to_ascii_lowercase());
+            } else if !name.is_empty() && !name.ends_with('-') {
+                name.push('-');
+            }
+        }
+        if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+        name.push_str(&self.count.to_string());
+        name
+    }
+
+    /// Writes the ground truth in a new directory of `output`, see the [module](self)
+    /// for the layout, and returns the path of this directory.
+    pub fn write_into(&self, output: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+        let directory = output.as_ref().join(self.directory_name());
+        let ann = directory.join("ann");
+        fs::create_dir_all(&ann).with_context(|| format!("while creating {}", ann.display()))?;
+
+        let neighbours: Vec<Vec<i32>> =
+            self.neighbours.iter().map(|ids| ids.iter().map(|&id| id as i32).collect()).collect();
+
+        write_file(&directory.join("queries.fvecs"), |w| Ok(write_vecs(w, &self.queries)?))?;
+        write_file(&directory.join("neighbours.ivecs"), |w| Ok(write_vecs(w, &neighbours)?))?;
+        write_file(&directory.join("distances.fvecs"), |w| Ok(write_vecs(w, &self.distances)?))?;
+
+        write_file(&ann.join("test.npy"), |w| write_npy(w, NpyDtype::F32, &self.queries))?;
+        write_file(&ann.join("neighbors.npy"), |w| write_npy(w, NpyDtype::I32, &neighbours))?;
+        write_file(&ann.join("distances.npy"), |w| write_npy(w, NpyDtype::F32, &self.distances))?;
+
+        let attributes = Attributes {
+            distance: ann_distance(self.distance).to_string(),
+            dimension: self.dimensions,
+            point_type: "float".to_string(),
+            dataset: self.dataset.clone(),
+            count: self.count,
+            checksum: self.checksum,
+            seed: self.seed,
+            query_ids: self.query_ids.clone(),
+        };
+        write_file(&ann.join("attrs.json"), |w| {
+            serde_json::to_writer_pretty(&mut *w, &attributes)?;
+            Ok(writeln!(w)?)
+        })?;
+
+        Ok(directory)
+    }
+}
+
+/// The nearest neighbours of a set of imported queries, computed by another tool.
+#[derive(Debug, Clone)]
+pub struct GroundTruthFile {
+    pub path: PathBuf,
+    /// The number of vectors among which the neighbours were searched,
+    /// the whole dataset when not specified by the file.
+    pub count: Option<usize>,
+    /// The distance used to find the neighbours, if specified by the file.
+    pub distance: Option<ScenarioDistance>,
+    /// The checksum of the dataset the neighbours were searched in, if specified by the file.
+    pub checksum: Option<u32>,
+    /// The ids of the nearest neighbours of every query, from the nearest to the farthest.
+    pub neighbours: Vec<Vec<u32>>,
+}
+
+impl GroundTruthFile {
+    /// Reads the neighbours of an `.ivecs` file, of a `.npy` array of `i32` or of a
+    /// directory written by [`GroundTruth::write_into`], or of its `ann` directory.
+    ///
+    /// Only the directories specify the count, distance and dataset of the ground truth.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<GroundTruthFile> {
+        let path = path.as_ref();
+        let (neighbours, attributes) = if path.is_dir() {
+            let (neighbours, attributes) = if path.join("neighbours.ivecs").exists() {
+                (read_ivecs(&path.join("neighbours.ivecs"))?, path.join("ann").join("attrs.json"))
+            } else {
+                (read_npy(&path.join("neighbors.npy"))?, path.join("attrs.json"))
+            };
+            let content = fs::read_to_string(&attributes)
+                .with_context(|| format!("while reading {}", attributes.display()))?;
+            let attributes: Attributes = serde_json::from_str(&content)
+                .with_context(|| format!("while parsing {}", attributes.display()))?;
+            (neighbours, Some(attributes))
+        } else {
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("ivecs") => (read_ivecs(path)?, None),
+                Some("npy") => (read_npy(path)?, None),
+                _ => bail!(
+                    "{} is not a `.ivecs` or `.npy` file nor an exported ground truth",
+                    path.display()
+                ),
+            }
+        };
+
+        let distance = match &attributes {
+            Some(attributes) => Some(
+                enum_iterator::all::<ScenarioDistance>()
+                    .find(|&distance| ann_distance(distance) == attributes.distance)
+                    .with_context(|| {
+                        format!(
+                            "{} was computed with the unsupported `{}` distance",
+                            path.display(),
+                            attributes.distance
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(GroundTruthFile {
+            path: path.to_path_buf(),
+            count: attributes.as_ref().map(|a| a.count),
+            distance,
+            checksum: attributes.as_ref().map(|a| a.checksum),
+            neighbours,
+        })
+    }
+
+    /// The smallest number of neighbours of a query.
+    pub fn nb_neighbours(&self) -> usize {
+        self.neighbours.iter().map(Vec::len).min().unwrap_or_default()
+    }
+
+    /// Checks that the neighbours of the `queries` are among the `count` first vectors of the
+    /// dataset and that they are sorted from the nearest to the farthest according to the distance `D`.
+    pub fn check<D: crate::Distance>(
+        &self,
+        dataset: &dyn VectorDataset,
+        count: usize,
+        queries: &[&[f32]],
+    ) -> anyhow::Result<()> {
+        // The neighbours computed by other tools can be swapped when their distances are really close.
+        const TOLERANCE: f32 = 1e-4;
+
+        for (i, (query, neighbours)) in queries.iter().zip(&self.neighbours).enumerate() {
+            if let Some(&id) = neighbours.iter().find(|&&id| id as usize >= count) {
+                bail!(
+                    "The query {i} of {} has the neighbour {id} but only {count} vectors are indexed, \
+                     the ground truth was not computed on {count} vectors",
+                    self.path.display()
+                );
+            }
+            let distances: Vec<_> = neighbours
+                .iter()
+                .map(|&id| crate::distance::<D>(query, &dataset.vector(id as usize).unwrap()))
+                .collect();
+            if let Some(rank) = distances.windows(2).position(|w| w[1] < w[0] - TOLERANCE) {
+                bail!(
+                    "The neighbour {} of the query {i} of {} is nearer than the one before it, \
+                     the ground truth was computed for other queries or with another distance",
+                    rank + 1,
+                    self.path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of the distance in the attributes of the ann-benchmarks HDF5 files.
+fn ann_distance(distance: ScenarioDistance) -> &'static str {
+    match distance {
+        ScenarioDistance::Cosine => "angular",
+    }
+}
+
+fn read_ivecs(path: &Path) -> anyhow::Result<Vec<Vec<u32>>> {
+    let ivecs = IvecsView::open(path, None)?;
+    ivecs.iter().map(|ids| to_ids(ids, path)).collect()
+}
+
+/// Reads a two-dimensional `.npy` array of `i32`, as saved by `np.save("neighbors.npy", ids)`.
+fn read_npy(path: &Path) -> anyhow::Result<Vec<Vec<u32>>> {
+    let bytes = fs::read(path).with_context(|| format!("while reading {}", path.display()))?;
+    let header = NpyHeader::decode(&bytes)
+        .with_context(|| format!("while reading the header of {}", path.display()))?;
+    ensure!(
+        header.dtype == NpyDtype::I32,
+        "{} contains {} values instead of the i32 ids of the neighbours",
+        path.display(),
+        header.dtype,
+    );
+    let data = bytes
+        .get(header.data_offset..header.data_offset + header.data_len())
+        .with_context(|| format!("{} is truncated", path.display()))?;
+    data.chunks_exact(header.dimensions * NpyDtype::I32.size())
+        .map(|row| {
+            let ids: Vec<i32> =
+                row.chunks_exact(4).map(|x| i32::from_le_bytes(x.try_into().unwrap())).collect();
+            to_ids(&ids, path)
+        })
+        .collect()
+}
+
+fn to_ids(ids: &[i32], path: &Path) -> anyhow::Result<Vec<u32>> {
+    ids.iter()
+        .map(|&id| {
+            u32::try_from(id)
+                .with_context(|| format!("{} contains the negative id {id}", path.display()))
+        })
+        .collect()
+}
+
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("while creating {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer).with_context(|| format!("while writing {}", path.display()))?;
+    writer.flush().with_context(|| format!("while writing {}", path.display()))
+}
+
+/// Writes the rows, that must all have the same length, as a two-dimensional `.npy` array.
+fn write_npy<T: bytemuck::NoUninit>(
+    mut writer: impl Write,
+    dtype: NpyDtype,
+    rows: &[Vec<T>],
+) -> anyhow::Result<()> {
+    let dimensions = rows.first().map_or(0, Vec::len);
+    writer.write_all(&NpyHeader::new(dtype, rows.len(), dimensions).encode())?;
+    for row in rows {
+        writer.write_all(bytemuck::cast_slice(row))?;
+    }
+    Ok(())
+}
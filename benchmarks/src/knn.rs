@@ -0,0 +1,150 @@
+//! The exact nearest neighbours of a set of queries, computed without loading the dataset.
+//!
+//! The vectors are streamed in blocks and every query keeps the best neighbours found so far
+//! in a heap, so the memory used only depends on the size of the blocks and of the heaps,
+//! not on the number of vectors.
+//!
+//! The candidates made of a ratio of the nearest vectors are too many to be kept in heaps,
+//! they are selected among the distances to every vector, for as many queries at a time
+//! as the memory allows.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::mem;
+
+use anyhow::ensure;
+use byte_unit::{Byte, UnitType};
+use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use roaring::RoaringBitmap;
+
+use crate::VectorDataset;
+
+/// Finds the `k` nearest neighbours of every query among the `count` first vectors of the
+/// dataset, or only among the `candidates` if specified. Returns the ids of the neighbours
+/// with their distance, from the nearest to the farthest.
+///
+/// The vectors are read in blocks and `memory` bounds the size of a block, converted to `f32`,
+/// plus the size of the heaps of every query.
+pub fn exact_nearest_neighbours<D: crate::Distance>(
+    dataset: &dyn VectorDataset,
+    count: usize,
+    queries: &[&[f32]],
+    k: usize,
+    candidates: Option<&RoaringBitmap>,
+    memory: usize,
+) -> anyhow::Result<Vec<Vec<(u32, f32)>>> {
+    let count = count.min(dataset.len());
+    let heaps_size = queries.len() * k * mem::size_of::<(OrderedFloat<f32>, u32)>();
+    let vector_size = dataset.dimensions() * mem::size_of::<f32>();
+    ensure!(
+        memory >= heaps_size + vector_size,
+        "{} are not enough to keep the {k} nearest neighbours of {} queries, at least {} are required",
+        format_bytes(memory),
+        queries.len(),
+        format_bytes(heaps_size + vector_size),
+    );
+    let block_len = ((memory - heaps_size) / vector_size).clamp(1, count.max(1));
+
+    let mut heaps: Vec<BinaryHeap<(OrderedFloat<f32>, u32)>> =
+        queries.iter().map(|_| BinaryHeap::with_capacity(k + 1)).collect();
+    for start in (0..count).step_by(block_len) {
+        let end = (start + block_len).min(count);
+        let block: Vec<_> = (start..end)
+            .filter(|&i| candidates.is_none_or(|c| c.contains(i as u32)))
+            .map(|i| (i as u32, dataset.vector(i).unwrap()))
+            .collect();
+
+        heaps.par_iter_mut().zip(queries).for_each(|(heap, query)| {
+            for (id, vector) in &block {
+                let neighbour = (OrderedFloat(crate::distance::<D>(query, vector)), *id);
+                if heap.len() < k {
+                    heap.push(neighbour);
+                } else if let Some(mut farthest) = heap.peek_mut() {
+                    if neighbour < *farthest {
+                        *farthest = neighbour;
+                    }
+                }
+            }
+        });
+        tracing::debug!("Searched the nearest neighbours among {end} of {count} vectors");
+    }
+
+    Ok(heaps
+        .into_iter()
+        .map(|heap| {
+            heap.into_sorted_vec().into_iter().map(|(distance, id)| (id, distance.0)).collect()
+        })
+        .collect())
+}
+
+/// Returns, for every query, the ids of its `lens[j]` nearest vectors among the `count` first
+/// vectors of the dataset, in the order of `lens`. The ties are broken by id like
+/// [`exact_nearest_neighbours`] so the nearest neighbours are always candidates.
+///
+/// `memory` bounds the size of the distances of the queries processed at the same time plus
+/// the size of a block of vectors, converted to `f32`.
+pub fn nearest_candidates<D: crate::Distance>(
+    dataset: &dyn VectorDataset,
+    count: usize,
+    queries: &[&[f32]],
+    lens: &[usize],
+    memory: usize,
+) -> anyhow::Result<Vec<Vec<RoaringBitmap>>> {
+    let count = count.min(dataset.len());
+    let distances_size = count * mem::size_of::<(OrderedFloat<f32>, u32)>();
+    let vector_size = dataset.dimensions() * mem::size_of::<f32>();
+    ensure!(
+        memory >= distances_size + vector_size,
+        "{} are not enough to select the candidates of a query among {count} vectors, at least {} are required",
+        format_bytes(memory),
+        format_bytes(distances_size + vector_size),
+    );
+    let group_len = ((memory - vector_size) / distances_size.max(1)).clamp(1, queries.len().max(1));
+    let block_len = ((memory - group_len * distances_size) / vector_size).clamp(1, count.max(1));
+
+    // The candidates are selected from the largest to the smallest among the previous ones.
+    let mut order: Vec<_> = (0..lens.len()).collect();
+    order.sort_by_key(|&j| Reverse(lens[j]));
+
+    let mut candidates = Vec::with_capacity(queries.len());
+    for (i, group) in queries.chunks(group_len).enumerate() {
+        let mut distances: Vec<Vec<(OrderedFloat<f32>, u32)>> =
+            group.iter().map(|_| Vec::with_capacity(count)).collect();
+        for start in (0..count).step_by(block_len) {
+            let end = (start + block_len).min(count);
+            let block: Vec<_> =
+                (start..end).map(|i| (i as u32, dataset.vector(i).unwrap())).collect();
+            distances.par_iter_mut().zip(group).for_each(|(distances, query)| {
+                distances.extend(
+                    block.iter().map(|(id, vector)| {
+                        (OrderedFloat(crate::distance::<D>(query, vector)), *id)
+                    }),
+                );
+            });
+        }
+
+        candidates.par_extend(distances.into_par_iter().map(|mut distances| {
+            let mut bitmaps = vec![RoaringBitmap::new(); lens.len()];
+            for &j in &order {
+                if lens[j] < distances.len() {
+                    distances.select_nth_unstable(lens[j]);
+                    distances.truncate(lens[j]);
+                }
+                bitmaps[j] = distances.iter().map(|&(_, id)| id).collect();
+            }
+            bitmaps
+        }));
+        tracing::debug!(
+            "Selected the candidates of {} of {} queries",
+            (i * group_len + group.len()),
+            queries.len()
+        );
+    }
+
+    Ok(candidates)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    format!("{:#.2}", Byte::from_u64(bytes as u64).get_appropriate_unit(UnitType::Binary))
+}
@@ -1,20 +1,55 @@
 #![allow(clippy::type_complexity)]
 
+#[cfg(feature = "arroy-0-5")]
+pub mod arroy05_bench;
 pub mod arroy_bench;
+mod attributes;
+mod compare;
 mod dataset;
+mod describe;
+mod experiment;
+mod ground_truth;
+mod knn;
+mod manifest;
+mod npy;
+mod plan;
 mod qdrant_bench;
+mod registry;
 pub mod scenarios;
+mod shards;
+mod synthetic;
+mod texmex;
+mod transform;
+mod validate;
 
 use std::fmt;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use arroy::distances::*;
+pub use attributes::*;
 use byte_unit::rust_decimal::Decimal;
 use byte_unit::{Byte, Unit, UnitType};
+pub use compare::*;
 pub use dataset::*;
+pub use describe::*;
+pub use experiment::*;
+pub use ground_truth::*;
+pub use knn::*;
+pub use manifest::*;
+pub use npy::*;
+pub use plan::*;
 use qdrant_client::qdrant::quantization_config;
-
+pub use registry::*;
+pub use shards::*;
+pub use synthetic::*;
+pub use texmex::*;
+pub use transform::*;
+pub use validate::*;
+
+/// The seed used to pick the queries among the indexed vectors.
 pub const RNG_SEED: u64 = 38;
+/// The seed given to the arroy builder when generating the trees.
+pub const ARROY_SEED: u64 = 13;
 
 /// A generalist distance trait that contains the informations required to configure every engine
 pub trait Distance {
@@ -62,6 +97,7 @@ pub fn distance<D: crate::Distance>(left: &[f32], right: &[f32]) -> f32 {
     D::real_distance(left, right)
 }
 
+#[derive(Clone, Copy)]
 pub struct Recall(pub f32);
 
 impl fmt::Debug for Recall {
@@ -96,6 +132,12 @@ pub struct IndexingMetrics {
     nb_trees: Vec<usize>,
 }
 
+impl Default for IndexingMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IndexingMetrics {
     pub fn new() -> Self {
         Self {
@@ -139,6 +181,36 @@ impl IndexingMetrics {
     pub fn end(&mut self) {
         self.end = Instant::now();
     }
+
+    /// The total time spent indexing, including the sleeps between the chunks.
+    pub fn total_duration(&self) -> Duration {
+        self.end.duration_since(self.start)
+    }
+
+    /// The time spent inserting the vectors of each chunk.
+    pub fn insert_durations(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.insert_durations.iter().map(|(start, end)| end.duration_since(*start))
+    }
+
+    /// The time spent building the trees of each chunk.
+    pub fn build_durations(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.build_durations.iter().map(|(start, end)| end.duration_since(*start))
+    }
+
+    /// The number of vectors indexed after each chunk.
+    pub fn nb_vectors(&self) -> &[usize] {
+        &self.nb_vectors
+    }
+
+    /// The size of the database in bytes after each chunk.
+    pub fn database_sizes(&self) -> &[usize] {
+        &self.database_size
+    }
+
+    /// The number of trees generated by arroy after each chunk.
+    pub fn nb_trees(&self) -> &[usize] {
+        &self.nb_trees
+    }
 }
 
 impl fmt::Display for IndexingMetrics {
@@ -209,7 +281,7 @@ impl fmt::Display for IndexingMetrics {
             }
             write!(f, "{nb_vectors:>max_length$}")?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         write!(f, "  => Insertions: ")?;
         for (idx, (insert, max_length)) in insertions.iter().zip(max_lengths.iter()).enumerate() {
@@ -218,7 +290,7 @@ impl fmt::Display for IndexingMetrics {
             }
             write!(f, "{insert:>max_length$}")?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         write!(f, "  => Builds:     ")?;
         for (idx, (build, max_length)) in builds.iter().zip(max_lengths.iter()).enumerate() {
@@ -227,7 +299,7 @@ impl fmt::Display for IndexingMetrics {
             }
             write!(f, "{build:>max_length$}")?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         write!(f, "  => Trees:      ")?;
         for (idx, (nb_trees, max_length)) in trees.iter().zip(max_lengths.iter()).enumerate() {
@@ -236,7 +308,7 @@ impl fmt::Display for IndexingMetrics {
             }
             write!(f, "{nb_trees:>max_length$}")?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
 
         write!(f, "  => Db size:    ")?;
         for (idx, (database_size, max_length)) in db_size.iter().zip(max_lengths.iter()).enumerate()
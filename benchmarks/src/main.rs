@@ -1,20 +1,18 @@
-use std::collections::HashMap;
-use std::fmt::Write as _;
-
-use arroy::distances::Cosine;
-use benchmarks::scenarios::ScenarioSearch;
-use benchmarks::{arroy_bench, scenarios, MatLEView, RNG_SEED};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use benchmarks::scenarios::{
+    self, ScenarioContender, ScenarioDistance, ScenarioFiltering, ScenarioOversampling,
+};
+use benchmarks::{
+    AttributeFilter, CompareThresholds, DatasetDescription, DatasetFile, DatasetRegistry,
+    DatasetReport, DatasetSource, DescribeOptions, Experiment, Manifest, QuerySpec, ResultFile,
+    Seeds, SyntheticDataset, SyntheticKind, SyntheticSpec, Transform, VectorDataset,
+};
 use byte_unit::Byte;
-use clap::Parser;
-use enum_iterator::Sequence;
-use itertools::{iproduct, Itertools};
-use ordered_float::OrderedFloat;
-use rand::rngs::StdRng;
-use rand::seq::SliceRandom as _;
-use rand::SeedableRng;
-use rayon::slice::ParallelSliceMut;
-use roaring::RoaringBitmap;
-use slice_group_by::GroupBy;
+use clap::{Parser, Subcommand};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 fn parse_number_with_underscores(s: &str) -> Result<usize, std::num::ParseIntError> {
@@ -22,14 +20,272 @@ fn parse_number_with_underscores(s: &str) -> Result<usize, std::num::ParseIntErr
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compares two result files and exits with an error if the recall regressed.
+    Compare(CompareArgs),
+    /// Scans datasets for NaNs, zero vectors and duplicates and exits with an error
+    /// if they cannot be benchmarked.
+    ValidateDataset(ValidateDatasetArgs),
+    /// Estimates the intrinsic dimensionality, hubness, contrast and cluster structure
+    /// of datasets on a sample and writes them as CSV.
+    DescribeDataset(DescribeDatasetArgs),
+    /// Generates a synthetic dataset and writes it into a `.mat` file.
+    GenerateDataset(GenerateDatasetArgs),
+    /// Computes the exact nearest neighbours of the queries for every count and writes them
+    /// as `.fvecs` and `.ivecs` files and as `.npy` files named like in ann-benchmarks.
+    ExportGroundTruth(ExportGroundTruthArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportGroundTruthArgs {
+    /// A TOML file describing the experiment whose ground truth is exported,
+    /// it replaces the datasets and counts arguments.
+    #[arg(
+        long,
+        conflicts_with_all = ["datasets", "dataset_path", "dataset_name", "dimensions", "count"]
+    )]
+    experiment: Option<PathBuf>,
+
+    /// The built-in or registered datasets to export, all the built-in ones if empty.
+    #[arg(long, value_delimiter = ',')]
+    datasets: Vec<DatasetSource>,
+
+    /// A `.mat`, `.npy`, `.npz`, `.fvecs`, `.bvecs` or `.shards.toml` file to export instead of the built-in datasets.
+    #[arg(long)]
+    dataset_path: Option<PathBuf>,
+
+    /// The name of the `--dataset-path` file, the one of its header or its file name by default.
+    #[arg(long, requires = "dataset_path")]
+    dataset_name: Option<String>,
+
+    /// The number of dimensions of the dataset files generated without header.
+    #[arg(long)]
+    dimensions: Option<usize>,
+
+    /// A TOML file listing named datasets, `datasets.toml` is used if it exists.
+    #[arg(long)]
+    registry: Option<PathBuf>,
+
+    /// The numbers of documents among which the neighbours are searched.
+    #[arg(long, value_delimiter = ',', value_parser = parse_number_with_underscores, required_unless_present = "experiment")]
+    count: Vec<usize>,
+
+    /// The number of queries, the one of the experiment or 100 by default.
+    #[arg(long)]
+    nb_queries: Option<usize>,
+
+    /// The number of neighbours exported for each query, the largest recall tested by default.
+    #[arg(long)]
+    neighbours: Option<usize>,
+
+    /// The number of threads used to compute the distances, all of them if not specified.
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Memory available to compute the nearest neighbours, the vectors are read in blocks
+    /// fitting in it. Unlimited if not specified.
+    #[arg(long)]
+    ground_truth_memory: Option<Byte>,
+
+    /// The directory in which a directory is created for every dataset and count.
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct GenerateDatasetArgs {
+    /// The distribution the vectors are drawn from.
+    #[arg(value_enum)]
+    kind: SyntheticKind,
+
+    /// The number of dimensions of the vectors.
+    #[arg(long)]
+    dimensions: usize,
+
+    /// The number of vectors to generate.
+    #[arg(long, value_parser = parse_number_with_underscores)]
+    count: usize,
+
+    /// The number of clusters of a mixture.
+    #[arg(long)]
+    clusters: Option<usize>,
+
+    /// The rank of a low-rank dataset.
+    #[arg(long)]
+    rank: Option<usize>,
+
+    /// The seed used to generate the vectors.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// The `.mat` file to write.
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct DescribeDatasetArgs {
+    /// The `.mat`, `.npy`, `.npz`, `.fvecs`, `.bvecs` or `.shards.toml` files to describe.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// The number of dimensions of the dataset files generated without header.
+    #[arg(long)]
+    dimensions: Option<usize>,
+
+    /// The number of vectors randomly picked in each dataset.
+    #[arg(long, default_value_t = DescribeOptions::default().sample, value_parser = parse_number_with_underscores)]
+    sample: usize,
+
+    /// The number of neighbours used to estimate the dimensionality and the hubness.
+    #[arg(long, default_value_t = DescribeOptions::default().k)]
+    k: usize,
+
+    /// The number of clusters searched in the sample.
+    #[arg(long, default_value_t = DescribeOptions::default().clusters)]
+    clusters: usize,
+
+    /// The seed used to pick the sample and initialize the clusters.
+    #[arg(long, default_value_t = DescribeOptions::default().seed)]
+    seed: u64,
+
+    /// The CSV file to write the descriptions to, the standard output if not specified.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateDatasetArgs {
+    /// The `.mat`, `.npy`, `.npz`, `.fvecs`, `.bvecs` or `.shards.toml` files to validate.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// The number of dimensions of the dataset files generated without header.
+    #[arg(long)]
+    dimensions: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareArgs {
+    /// The reference result file.
+    old: PathBuf,
+
+    /// The result file to compare with the reference.
+    new: PathBuf,
+
+    /// The maximum drop of any recall before it is considered a regression.
+    #[arg(long, default_value_t = 0.02)]
+    recall_threshold: f32,
+
+    /// The maximum increase of the database size, in percent, before it is considered a regression.
+    #[arg(long)]
+    size_threshold: Option<f64>,
+
+    /// The maximum increase of the indexing or search time, in percent, before it is considered a regression.
+    #[arg(long)]
+    time_threshold: Option<f64>,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// The datasets to run and all of them are ran if empty.
-    #[arg(long, value_enum)]
-    datasets: Vec<scenarios::Dataset>,
+    /// A TOML file describing the experiment to run, it replaces the datasets, counts,
+    /// number of trees, chunks, memory and recall arguments.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "datasets",
+            "dataset_path",
+            "dataset_name",
+            "synthetic",
+            "dimensions",
+            "truncate_dims",
+            "transforms",
+            "cache_transforms",
+            "count",
+            "nb_trees",
+            "number_of_chunks",
+            "sleep_between_chunks",
+            "memory",
+            "recall_tested",
+            "filter",
+            "queries",
+            "ground_truth",
+        ]
+    )]
+    experiment: Option<PathBuf>,
+
+    /// The datasets to run and all the built-in ones are ran if empty.
+    ///
+    /// Either a built-in dataset (hn-posts, datacomp-small, wikipedia, hn-top-post,
+    /// db-pedia-ada002, db-pedia3-large) or the name of a dataset of the registry.
+    #[arg(long, value_delimiter = ',')]
+    datasets: Vec<DatasetSource>,
 
-    /// Ignored
-    #[arg(long, value_enum)]
+    /// A `.mat`, `.npy`, `.npz`, `.fvecs`, `.bvecs` or `.shards.toml` file to run instead of the built-in datasets.
+    #[arg(long)]
+    dataset_path: Option<PathBuf>,
+
+    /// The name of the `--dataset-path` file shown in the results,
+    /// the one of its header or its file name by default.
+    #[arg(long, requires = "dataset_path")]
+    dataset_name: Option<String>,
+
+    /// Generates the vectors instead of reading a dataset, their number is the largest `--count`.
+    #[arg(long, value_enum, requires = "dimensions")]
+    synthetic: Option<SyntheticKind>,
+
+    /// The number of clusters of a `--synthetic mixture`.
+    #[arg(long)]
+    clusters: Option<usize>,
+
+    /// The rank of a `--synthetic low-rank`.
+    #[arg(long)]
+    rank: Option<usize>,
+
+    /// The seed used to generate the `--synthetic` vectors.
+    #[arg(long)]
+    synthetic_seed: Option<u64>,
+
+    /// The number of dimensions of the dataset files generated without header
+    /// or of the `--synthetic` vectors.
+    #[arg(long)]
+    dimensions: Option<usize>,
+
+    /// The transforms applied, in order, to the vectors before indexing them:
+    /// `normalize`, `center`, `pca:<dimensions>`, `gaussian-projection:<dimensions>`
+    /// or `sparse-projection:<dimensions>`.
+    #[arg(long, value_delimiter = ',')]
+    transforms: Vec<Transform>,
+
+    /// Writes the transformed vectors into a `.mat` file next to the dataset
+    /// and reads them from it the next times.
+    #[arg(long, requires = "transforms")]
+    cache_transforms: bool,
+
+    /// Truncates the vectors to these numbers of dimensions and renormalizes them,
+    /// for the Matryoshka embeddings. The dataset is benchmarked once per number of dimensions.
+    #[arg(long, value_delimiter = ',')]
+    truncate_dims: Vec<usize>,
+
+    /// A TOML file listing named datasets, `datasets.toml` is used if it exists.
+    #[arg(long)]
+    registry: Option<PathBuf>,
+
+    /// The contenders to benchmark on the same queries, only arroy if empty.
+    /// They replace the ones of the `--experiment` if specified.
+    ///
+    /// The older versions of arroy must be enabled with their cargo feature, e.g. `--features arroy-0-5`.
+    #[arg(long, value_enum, value_delimiter = ',')]
     contenders: Vec<scenarios::ScenarioContender>,
 
     /// Ignored
@@ -44,10 +300,30 @@ struct Args {
     #[arg(long, value_enum)]
     filterings: Vec<scenarios::ScenarioFiltering>,
 
+    /// Only searches among the documents whose attributes match, e.g. `lang = en AND year >= 2020`.
+    ///
+    /// The attributes are read from the `.attributes.jsonl` file next to the dataset,
+    /// written by `parquet2mat --id-column --attribute-columns`.
+    #[arg(long)]
+    filter: Option<AttributeFilter>,
+
     /// The list of recall to be tested.
     #[arg(long, default_value_t = String::from("1,10,20,50,100,500"))]
     recall_tested: String,
 
+    /// A `.mat`, `.npy`, `.npz`, `.fvecs` or `.bvecs` file whose vectors are searched
+    /// instead of queries picked among the indexed vectors.
+    #[arg(long)]
+    queries: Option<PathBuf>,
+
+    /// The nearest neighbours of the `--queries`, computed beforehand: `.ivecs` files, `.npy`
+    /// arrays of `i32` or directories written by `export-ground-truth`.
+    ///
+    /// The `.ivecs` and `.npy` files must contain the neighbours among the whole dataset,
+    /// the ones of the counts not covered by these files are computed.
+    #[arg(long, value_delimiter = ',', requires = "queries")]
+    ground_truth: Vec<PathBuf>,
+
     /// Set the different number of documents to evaluate from the dataset.
     #[arg(long, value_delimiter = ',', value_parser = parse_number_with_underscores)]
     count: Vec<usize>,
@@ -73,38 +349,226 @@ struct Args {
     #[arg(long, default_value_t = Byte::MAX)]
     memory: Byte,
 
+    /// Memory available to compute the exact nearest neighbours of the queries.
+    ///
+    /// The vectors are read in blocks fitting in it instead of all at once,
+    /// which makes the counts larger than the RAM evaluable. Unlimited if not specified.
+    #[arg(long)]
+    ground_truth_memory: Option<Byte>,
+
     /// The number of threads to use for indexing. If not specified the maximum number of threads will be used.
     #[arg(long)]
     threads: Option<usize>,
 
+    /// The CSV file to write the results to, the standard output if not specified.
+    #[arg(long)]
+    output: Option<PathBuf>,
+
     /// When set to true, will print all the steps it goes through.
     #[arg(long, default_value_t = false)]
     verbose: bool,
 }
 
 fn main() {
+    let Cli { command, args } = Cli::parse();
+    match command {
+        Some(Command::Compare(args)) => compare(args),
+        Some(Command::ValidateDataset(args)) => validate_dataset(args),
+        Some(Command::DescribeDataset(args)) => describe_dataset(args),
+        Some(Command::GenerateDataset(args)) => generate_dataset(args),
+        Some(Command::ExportGroundTruth(args)) => export_ground_truth(args),
+        None => run(args),
+    }
+}
+
+fn compare(args: CompareArgs) {
+    let CompareArgs { old, new, recall_threshold, size_threshold, time_threshold } = args;
+    let old = ResultFile::from_path(old).unwrap();
+    let new = ResultFile::from_path(new).unwrap();
+    let thresholds = CompareThresholds {
+        recall: recall_threshold,
+        size: size_threshold.map(|t| t / 100.0),
+        time: time_threshold.map(|t| t / 100.0),
+    };
+
+    let regressions = benchmarks::compare(&old, &new, thresholds);
+    if regressions != 0 {
+        eprintln!("Found {regressions} regressions");
+        std::process::exit(1);
+    }
+}
+
+fn validate_dataset(args: ValidateDatasetArgs) {
+    let ValidateDatasetArgs { paths, dimensions } = args;
+
+    let mut invalid = 0;
+    for path in paths {
+        let dataset = match benchmarks::open_dataset(&path, None, dimensions) {
+            Ok(dataset) => dataset,
+            Err(error) => {
+                println!("{}\n  \x1b[1;31merror\x1b[0m: {error:#}\n", path.display());
+                invalid += 1;
+                continue;
+            }
+        };
+        let report = DatasetReport::new(dataset.as_ref());
+        println!("{report}");
+        invalid += report.has_errors() as usize;
+    }
+
+    if invalid != 0 {
+        eprintln!("Found {invalid} invalid datasets");
+        std::process::exit(1);
+    }
+}
+
+fn describe_dataset(args: DescribeDatasetArgs) {
+    let DescribeDatasetArgs { paths, dimensions, sample, k, clusters, seed, output } = args;
+    let options = DescribeOptions { sample, k, clusters, seed };
+
+    let mut output: Box<dyn Write> = match &output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("while creating {}", path.display()))
+                .unwrap(),
+        )),
+        None => Box::new(io::stdout()),
+    };
+
+    writeln!(output, "{}", DatasetDescription::csv_header()).unwrap();
+    for path in paths {
+        let dataset = benchmarks::open_dataset(&path, None, dimensions).unwrap();
+        let description = DatasetDescription::new(dataset.as_ref(), options).unwrap();
+        writeln!(output, "{}", description.csv_line()).unwrap();
+        output.flush().unwrap();
+    }
+}
+
+fn generate_dataset(args: GenerateDatasetArgs) {
+    let GenerateDatasetArgs { kind, dimensions, count, clusters, rank, seed, output } = args;
+    let mut spec = SyntheticSpec::new(kind, dimensions, count);
+    spec.clusters = clusters.unwrap_or(spec.clusters);
+    spec.rank = rank.unwrap_or(spec.rank);
+    spec.seed = seed.unwrap_or(spec.seed);
+
+    let dataset = SyntheticDataset::new(spec).unwrap();
+    let file = File::create(&output)
+        .with_context(|| format!("while creating {}", output.display()))
+        .unwrap();
+    dataset.write_into(BufWriter::new(file)).unwrap();
+    println!("{} written into {}", dataset.name(), output.display());
+}
+
+fn export_ground_truth(args: ExportGroundTruthArgs) {
+    let ExportGroundTruthArgs {
+        experiment,
+        mut datasets,
+        dataset_path,
+        dataset_name,
+        dimensions,
+        registry,
+        count,
+        nb_queries,
+        neighbours,
+        threads,
+        ground_truth_memory,
+        output,
+    } = args;
+
+    if let Some(path) = dataset_path {
+        datasets.push(DatasetSource::File(DatasetFile { name: dataset_name, path, dimensions }));
+    }
+    let mut experiment = match experiment {
+        Some(path) => Experiment::from_path(path).unwrap(),
+        None => Experiment {
+            datasets,
+            dimensions,
+            transforms: Vec::new(),
+            cache_transforms: false,
+            truncate_dims: Vec::new(),
+            count,
+            nb_trees: Vec::new(),
+            contenders: vec![ScenarioContender::Arroy],
+            distances: vec![ScenarioDistance::Cosine],
+            over_samplings: vec![ScenarioOversampling::X1],
+            filterings: vec![ScenarioFiltering::NoFilter],
+            attribute_filter: None,
+            number_of_chunks: vec![1],
+            sleep_between_chunks: 0,
+            memory: None,
+            ground_truth_memory: None,
+            threads: None,
+            queries: QuerySpec::default(),
+            seeds: Seeds::default(),
+            output: None,
+        },
+    };
+    experiment.queries.nb_queries = nb_queries.unwrap_or(experiment.queries.nb_queries);
+    if let Some(neighbours) = neighbours {
+        experiment.queries.recall_tested = vec![neighbours];
+    }
+    experiment.threads = threads.or(experiment.threads);
+    experiment.ground_truth_memory = ground_truth_memory.or(experiment.ground_truth_memory);
+
+    let registry = DatasetRegistry::load(registry.as_deref()).unwrap();
+    let experiment = experiment.resolve(&registry).unwrap();
+    if let Some(threads) = experiment.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
+    }
+
+    let plan = experiment.to_plan().unwrap();
+    plan.ground_truths(|ground_truth| {
+        let directory = ground_truth.write_into(&output)?;
+        println!(
+            "{} queries of {} among {} vectors written into {}",
+            ground_truth.queries.len(),
+            ground_truth.dataset,
+            ground_truth.count,
+            directory.display()
+        );
+        Ok(())
+    })
+    .unwrap();
+}
+
+fn run(args: Args) {
     let Args {
-        datasets,
+        experiment,
+        mut datasets,
+        dataset_path,
+        dataset_name,
+        synthetic,
+        clusters,
+        rank,
+        synthetic_seed,
+        dimensions,
+        transforms,
+        cache_transforms,
+        truncate_dims,
+        registry,
         count,
         nb_trees,
         number_of_chunks,
         contenders,
-        distances,
-        over_samplings,
-        filterings,
+        distances: _,
+        over_samplings: _,
+        filterings: _,
+        filter,
         sleep_between_chunks,
         memory,
+        ground_truth_memory,
         recall_tested,
+        queries,
+        ground_truth,
         threads,
+        output,
         verbose,
-    } = Args::parse();
+    } = args;
 
     if verbose {
         // Initialize tracing with the specified level
-        let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            let filter = format!("arroy=debug,benchmarks=debug");
-            EnvFilter::new(filter)
-        });
+        let env_filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("arroy=debug,benchmarks=debug"));
 
         FmtSubscriber::builder()
             .with_env_filter(env_filter)
@@ -114,16 +578,104 @@ fn main() {
             .init();
     }
 
-    if let Some(threads) = threads {
+    if let Some(path) = dataset_path {
+        datasets.push(DatasetSource::File(DatasetFile { name: dataset_name, path, dimensions }));
+    }
+    if let (Some(kind), Some(dimensions)) = (synthetic, dimensions) {
+        let count = count.iter().max().copied().unwrap_or_default();
+        let mut spec = SyntheticSpec::new(kind, dimensions, count);
+        spec.clusters = clusters.unwrap_or(spec.clusters);
+        spec.rank = rank.unwrap_or(spec.rank);
+        spec.seed = synthetic_seed.unwrap_or(spec.seed);
+        datasets.push(DatasetSource::Synthetic(spec));
+    }
+
+    let experiment = match experiment {
+        Some(path) => {
+            let mut experiment = Experiment::from_path(path).unwrap();
+            experiment.threads = threads.or(experiment.threads);
+            experiment.ground_truth_memory = ground_truth_memory.or(experiment.ground_truth_memory);
+            experiment.output = output.or(experiment.output);
+            if !contenders.is_empty() {
+                experiment.contenders = contenders;
+            }
+            experiment
+        }
+        None => Experiment {
+            datasets,
+            dimensions,
+            transforms,
+            cache_transforms,
+            truncate_dims,
+            count,
+            nb_trees,
+            contenders: if contenders.is_empty() {
+                vec![ScenarioContender::Arroy]
+            } else {
+                contenders
+            },
+            distances: vec![ScenarioDistance::Cosine],
+            over_samplings: vec![ScenarioOversampling::X1],
+            filterings: match filter {
+                Some(_) => vec![ScenarioFiltering::Attributes],
+                None => vec![ScenarioFiltering::NoFilter],
+            },
+            attribute_filter: filter,
+            number_of_chunks,
+            sleep_between_chunks,
+            memory: (memory != Byte::MAX).then_some(memory),
+            ground_truth_memory,
+            threads,
+            queries: QuerySpec {
+                recall_tested: parse_recall_tested(&recall_tested),
+                path: queries,
+                ground_truth,
+                ..QuerySpec::default()
+            },
+            seeds: Seeds::default(),
+            output,
+        },
+    };
+    let registry = DatasetRegistry::load(registry.as_deref()).unwrap();
+    let experiment = experiment.resolve(&registry).unwrap();
+
+    if let Some(threads) = experiment.threads {
         rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
     }
 
-    let datasets = set_or_all::<_, MatLEView<f32>>(datasets);
-    let contenders = vec![scenarios::ScenarioContender::Arroy];
-    let distances = vec![scenarios::ScenarioDistance::Cosine];
-    let over_samplings = vec![scenarios::ScenarioOversampling::X1];
-    let filterings = vec![scenarios::ScenarioFiltering::NoFilter];
-    let recall_tested: Vec<usize> = recall_tested
+    let plan = experiment.to_plan().unwrap().verbose(verbose);
+
+    let mut output: Box<dyn Write> = match &experiment.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("while creating {}", path.display()))
+                .unwrap(),
+        )),
+        None => Box::new(io::stdout()),
+    };
+
+    let manifest = Manifest::new(&experiment, plan.opened_datasets()).unwrap();
+    write!(output, "{}", manifest.to_csv_comment()).unwrap();
+    writeln!(output, "{}", plan.csv_header()).unwrap();
+
+    let mut current_group = None;
+    plan.run(|result| {
+        let group = (result.dataset.clone(), result.distance);
+        if current_group.as_ref().is_some_and(|current| *current != group) {
+            writeln!(output).unwrap();
+        }
+        current_group = Some(group);
+        writeln!(output, "{}", plan.csv_line(&result)).unwrap();
+        output.flush().unwrap();
+    })
+    .unwrap();
+
+    writeln!(output).unwrap();
+    output.flush().unwrap();
+}
+
+fn parse_recall_tested(recall_tested: &str) -> Vec<usize> {
+    recall_tested
         .split(',')
         .enumerate()
         .filter(|(_, n)| !n.trim().is_empty())
@@ -132,144 +684,5 @@ fn main() {
                 .parse()
                 .unwrap_or_else(|_| panic!("Could not parse recall value `{n}` at index `{i}`."))
         })
-        .collect();
-
-    assert!(datasets.len() == 1, "Cannot use more than one dataset");
-    assert!(number_of_chunks.len() == 1, "Cannot use more than one chunk");
-    assert!(!nb_trees.is_empty(), "Must specify at least one number of trees with --nb-trees 1,2,3");
-    assert!(!count.is_empty(), "Must specify at least one number of vectors with --count 1000,2000,3000");
-
-    let scenaris: Vec<_> = iproduct!(datasets, distances, contenders, over_samplings, filterings)
-        .map(|(dataset, distance, contender, oversampling, filtering)| {
-            (dataset, distance, contender, ScenarioSearch { oversampling, filtering })
-        })
-        .sorted()
-        .collect();
-
-    let mut header = String::new();
-    header.push_str(&format!("nb vectors,nb trees,db size in bytes,recall score,"));
-    recall_tested.iter().for_each(|recall| write!(&mut header, "recall@{recall},").unwrap());
-    let header = header.trim_end_matches(",");
-    println!("{header}");
-
-    for grp in scenaris
-        .linear_group_by(|(da, dia, ca, _), (db, dib, cb, _)| da == db && dia == dib && ca == cb)
-    {
-        for count in &count {
-            for nb_trees in &nb_trees {
-                // need to be filled up for the end log
-                let mut line = String::new();
-                line.push_str(&format!("{count},{nb_trees},"));
-
-                let (dataset, distance, contender, _) = &grp[0];
-                let search: Vec<&ScenarioSearch> = grp.iter().map(|(_, _, _, s)| s).collect();
-
-                let points: Vec<_> =
-                    dataset.iter().take(*count).enumerate().map(|(i, v)| (i as u32, v)).collect();
-                let memory = memory.as_u64() as usize;
-
-                let max = recall_tested.iter().max().copied().unwrap_or_default();
-                // If we have no recall we can skip entirely the generation of the queries
-                let queries = if max == 0 {
-                    Vec::new()
-                } else {
-                    let mut rng = StdRng::seed_from_u64(RNG_SEED);
-                    (0..100)
-                        .map(|_| points.choose(&mut rng).unwrap())
-                        .map(|(id, target)| {
-                            let mut points = points.clone();
-                            points.par_sort_unstable_by_key(|(_, v)| {
-                                OrderedFloat(benchmarks::distance::<Cosine>(target, v))
-                            });
-
-                            // We collect the different filtered versions here.
-                            let filtered: HashMap<_, _> = search
-                                .iter()
-                                .map(|ScenarioSearch { filtering, .. }| {
-                                    let candidates = match filtering {
-                                        scenarios::ScenarioFiltering::NoFilter => None,
-                                        filtering => {
-                                            let total = points.len() as f32;
-                                            let filtering = filtering.to_ratio_f32();
-                                            Some(
-                                                points
-                                                    .iter()
-                                                    .map(|(id, _)| id)
-                                                    .take((total * filtering) as usize)
-                                                    .collect::<RoaringBitmap>(),
-                                            )
-                                        }
-                                    };
-
-                                    // This is the real expected answer without the filtered out candidates.
-                                    let answer = points
-                                        .iter()
-                                        .map(|(id, _)| *id)
-                                        .filter(|&id| {
-                                            candidates.as_ref().map_or(true, |c| c.contains(id))
-                                        })
-                                        .take(max)
-                                        .collect::<Vec<_>>();
-
-                                    (*filtering, (candidates, answer))
-                                })
-                                .collect();
-
-                            (id, target, filtered)
-                        })
-                        .collect()
-                };
-
-                for number_of_chunks in &number_of_chunks {
-                    match contender {
-                        scenarios::ScenarioContender::Qdrant => {
-                            println!("Qdrant is not supported yet")
-                        }
-                        scenarios::ScenarioContender::Arroy => match distance {
-                            scenarios::ScenarioDistance::Cosine => {
-                                arroy_bench::prepare_and_run::<Cosine, _>(
-                                    &mut line,
-                                    &points,
-                                    Some(*nb_trees),
-                                    *number_of_chunks,
-                                    sleep_between_chunks,
-                                    memory,
-                                    verbose,
-                                    |line,time_to_index, env, database| {
-                                        arroy_bench::run_scenarios(
-                                            line,
-                                            env,
-                                            time_to_index,
-                                            distance,
-                                            *number_of_chunks,
-                                            &search,
-                                            &queries,
-                                            &recall_tested,
-                                            database,
-                                        );
-                                    },
-                                )
-                            }
-                        },
-                    }
-                }
-                let line =line.trim_end_matches(",");
-                println!("{line}");
-            }
-        }
-
-        println!();
-    }
-}
-
-fn set_or_all<S, T>(datasets: Vec<S>) -> Vec<T>
-where
-    S: Sequence,
-    S: Into<T>,
-{
-    if datasets.is_empty() {
-        enum_iterator::all::<S>().map(Into::into).collect()
-    } else {
-        datasets.into_iter().map(Into::into).collect()
-    }
+        .collect()
 }
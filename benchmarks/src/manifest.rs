@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{Experiment, VectorDataset};
+
+/// Everything required to know whether two result files are comparable.
+///
+/// It is written as a block of TOML comments at the top of every CSV we generate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    /// The resolved experiment that produced the results.
+    pub experiment: Experiment,
+    pub build: BuildInfo,
+    pub machine: MachineInfo,
+    pub datasets: Vec<DatasetInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildInfo {
+    /// The version of the benchmarks crate.
+    pub benchmarks: String,
+    /// The version of arroy, followed by its source when it doesn't come from crates.io.
+    pub arroy: String,
+    /// The version of arroy 0.5, when compiled with the `arroy-0-5` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arroy_0_5: Option<String>,
+    /// The commit of this repository, suffixed by `-dirty` if the compiled sources
+    /// had uncommitted changes.
+    pub commit: String,
+    /// Whether the benchmarks were compiled in `debug` or `release` mode.
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MachineInfo {
+    pub os: String,
+    pub arch: String,
+    pub cpu: String,
+    pub logical_cpus: usize,
+    /// The number of threads used by rayon to index and search.
+    pub threads: usize,
+    /// The memory available for indexing in bytes, `None` if unlimited.
+    pub indexing_memory: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DatasetInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub dimensions: usize,
+    /// The size of the file in bytes.
+    pub size: usize,
+    /// The CRC32 of the whole file.
+    pub checksum: String,
+}
+
+impl Manifest {
+    /// Gathers the information about the current build, machine and the `datasets` opened
+    /// for the experiment, e.g. by [`crate::BenchmarkPlan::opened_datasets`].
+    ///
+    /// Computing the checksums requires reading the datasets entirely, once per file
+    /// even when it is benchmarked truncated to several dimensions.
+    pub fn new(
+        experiment: &Experiment,
+        datasets: &[Arc<dyn VectorDataset>],
+    ) -> anyhow::Result<Manifest> {
+        let mut checksums = HashMap::new();
+        let datasets = datasets
+            .iter()
+            .map(|dataset| {
+                let checksum = *checksums
+                    .entry(dataset.path().to_path_buf())
+                    .or_insert_with(|| dataset.checksum());
+                DatasetInfo::new(dataset.as_ref(), checksum)
+            })
+            .collect();
+
+        Ok(Manifest {
+            experiment: experiment.clone(),
+            build: BuildInfo::current(),
+            machine: MachineInfo::current(experiment),
+            datasets,
+        })
+    }
+
+    /// Reads the manifest written at the top of a CSV file, if any.
+    pub fn from_csv(path: impl AsRef<Path>) -> anyhow::Result<Option<Manifest>> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+
+        let mut toml = String::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("while reading {}", path.display()))?;
+            match line.strip_prefix('#') {
+                Some(line) => {
+                    toml.push_str(line.strip_prefix(' ').unwrap_or(line));
+                    toml.push('\n');
+                }
+                None => break,
+            }
+        }
+
+        if toml.is_empty() {
+            return Ok(None);
+        }
+        let manifest = toml::from_str(&toml)
+            .with_context(|| format!("while parsing the manifest of {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    /// The manifest as a block of comments that can be prepended to a CSV file.
+    pub fn to_csv_comment(&self) -> String {
+        self.to_toml()
+            .lines()
+            .map(|line| if line.is_empty() { "#\n".to_string() } else { format!("# {line}\n") })
+            .collect()
+    }
+}
+
+impl BuildInfo {
+    pub fn current() -> BuildInfo {
+        BuildInfo {
+            benchmarks: env!("CARGO_PKG_VERSION").to_string(),
+            arroy: env!("BENCHMARKS_ARROY_VERSION").to_string(),
+            arroy_0_5: option_env!("BENCHMARKS_ARROY_0_5_VERSION").map(str::to_string),
+            commit: env!("BENCHMARKS_GIT_COMMIT").to_string(),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
+        }
+    }
+}
+
+impl MachineInfo {
+    pub fn current(experiment: &Experiment) -> MachineInfo {
+        MachineInfo {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpu: cpu_model().unwrap_or_else(|| String::from("unknown")),
+            logical_cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads: rayon::current_num_threads(),
+            indexing_memory: experiment.memory.map(|memory| memory.as_u64()),
+        }
+    }
+}
+
+impl DatasetInfo {
+    /// The information about the dataset, whose file has the given `checksum`.
+    pub fn new(dataset: &dyn VectorDataset, checksum: u32) -> DatasetInfo {
+        DatasetInfo {
+            name: dataset.name().to_string(),
+            path: dataset.path().to_path_buf(),
+            dimensions: dataset.dimensions(),
+            size: dataset.file_size(),
+            checksum: format!("{checksum:08x}"),
+        }
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "model name").then(|| value.trim().to_string())
+    })
+}
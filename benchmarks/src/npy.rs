@@ -0,0 +1,194 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context};
+use half::f16;
+use matfile::{NpyDtype, NpyHeader};
+use memmap2::Mmap;
+use zip::CompressionMethod;
+
+use crate::dataset::checksum;
+use crate::VectorDataset;
+
+/// A memory mapped NumPy `.npy` matrix, or one of the arrays of a `.npz` archive.
+///
+/// The `f16` and `f64` values are converted to `f32` on access.
+#[derive(Debug, Clone)]
+pub struct NpyView {
+    name: String,
+    path: PathBuf,
+    mmap: Arc<Mmap>,
+    /// The offset of the first vector in the file, right after the header.
+    offset: usize,
+    dtype: NpyDtype,
+    dimensions: usize,
+    len: usize,
+}
+
+impl NpyView {
+    /// Opens a `.npy` file, its shape must be `(rows, dimensions)`.
+    ///
+    /// If specified, the `dimensions` must match the ones of the file.
+    pub fn open(path: impl AsRef<Path>, dimensions: Option<usize>) -> anyhow::Result<NpyView> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        NpyView::from_mmap(path, mmap, 0, dimensions)
+    }
+
+    /// Opens an array of a `.npz` archive, the first one if the `array` is not specified.
+    ///
+    /// The array must be stored without compression, as done by `np.savez`,
+    /// so that it can be memory mapped.
+    pub fn open_npz(
+        path: impl AsRef<Path>,
+        array: Option<&str>,
+        dimensions: Option<usize>,
+    ) -> anyhow::Result<NpyView> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+        let mut archive = zip::ZipArchive::new(&file)
+            .with_context(|| format!("while reading the archive {}", path.display()))?;
+
+        let mut found = None;
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            let name = entry.name().trim_end_matches(".npy");
+            if array.is_none_or(|array| array == name) {
+                found = Some((name.to_string(), entry.compression(), entry.data_start()));
+                break;
+            }
+        }
+        let Some((name, compression, offset)) = found else {
+            bail!("{} doesn't contain the array `{}`", path.display(), array.unwrap_or_default());
+        };
+        ensure!(
+            compression == CompressionMethod::Stored,
+            "The array `{name}` of {} is compressed, save it with `np.savez` instead of `np.savez_compressed`",
+            path.display(),
+        );
+
+        let mmap = unsafe { Mmap::map(&file)? };
+        NpyView::from_mmap(path, mmap, offset as usize, dimensions)
+    }
+
+    fn from_mmap(
+        path: &Path,
+        mmap: Mmap,
+        start: usize,
+        dimensions: Option<usize>,
+    ) -> anyhow::Result<NpyView> {
+        let header = NpyHeader::decode(&mmap[start..])
+            .with_context(|| format!("while reading the header of {}", path.display()))?;
+        ensure!(
+            header.dtype != NpyDtype::I32,
+            "{} contains integers, like the ids of a ground truth, instead of vectors",
+            path.display(),
+        );
+        if let Some(dimensions) = dimensions {
+            ensure!(
+                header.dimensions == dimensions,
+                "{} contains vectors of {} dimensions but {dimensions} were expected",
+                path.display(),
+                header.dimensions,
+            );
+        }
+        let offset = start + header.data_offset;
+        ensure!(
+            mmap.len() >= offset + header.data_len(),
+            "{} is truncated, its header declares {} vectors of {} dimensions",
+            path.display(),
+            header.rows,
+            header.dimensions,
+        );
+
+        let name = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into());
+        Ok(NpyView {
+            name,
+            path: path.to_path_buf(),
+            mmap: Arc::new(mmap),
+            offset,
+            dtype: header.dtype,
+            dimensions: header.dimensions,
+            len: header.rows,
+        })
+    }
+
+    /// Replaces the name deduced from the file name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The type of the values stored in the file.
+    pub fn dtype(&self) -> NpyDtype {
+        self.dtype
+    }
+
+    fn bytes(&self, index: usize) -> Option<&[u8]> {
+        if index < self.len {
+            let vector_len = self.dimensions * self.dtype.size();
+            let start = self.offset + index * vector_len;
+            Some(&self.mmap[start..start + vector_len])
+        } else {
+            None
+        }
+    }
+}
+
+impl VectorDataset for NpyView {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn file_size(&self) -> usize {
+        self.mmap.len()
+    }
+
+    fn checksum(&self) -> u32 {
+        checksum(&self.mmap)
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        let bytes = self.bytes(index)?;
+        Some(match self.dtype {
+            NpyDtype::F32 => match bytemuck::try_cast_slice(bytes) {
+                Ok(vector) => Cow::Borrowed(vector),
+                // The arrays of the `.npz` archives are not always aligned.
+                Err(_) => Cow::Owned(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+                        .collect(),
+                ),
+            },
+            NpyDtype::F16 => Cow::Owned(
+                bytes
+                    .chunks_exact(2)
+                    .map(|x| f16::from_le_bytes(x.try_into().unwrap()).to_f32())
+                    .collect(),
+            ),
+            NpyDtype::F64 => Cow::Owned(
+                bytes
+                    .chunks_exact(8)
+                    .map(|x| f64::from_le_bytes(x.try_into().unwrap()) as f32)
+                    .collect(),
+            ),
+            NpyDtype::I32 => unreachable!("the arrays of integers are refused when opened"),
+        })
+    }
+}
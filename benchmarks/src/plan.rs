@@ -0,0 +1,905 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, ensure};
+use arroy::distances::Cosine;
+use itertools::{iproduct, Itertools};
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom as _;
+use rand::SeedableRng;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+
+use crate::scenarios::*;
+use crate::{
+    arroy_bench, exact_nearest_neighbours, nearest_candidates, AttributeFilter, GroundTruth,
+    GroundTruthFile, IndexingMetrics, Recall, Transform, VectorDataset, ARROY_SEED, RNG_SEED,
+};
+
+/// Describes how the queries are picked and evaluated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct QuerySpec {
+    /// The number of queries randomly picked among the indexed vectors.
+    pub nb_queries: usize,
+    /// The list of recall to be tested, e.g. `10` will compute the recall@10.
+    pub recall_tested: Vec<usize>,
+    /// A file whose vectors are the queries instead of the ones picked among the
+    /// indexed vectors, `nb-queries` is then ignored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// The nearest neighbours of the queries of `path` computed beforehand, see
+    /// [`GroundTruthFile::open`]. They are computed for the counts these files don't cover.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ground_truth: Vec<PathBuf>,
+}
+
+impl Default for QuerySpec {
+    fn default() -> Self {
+        QuerySpec {
+            nb_queries: 100,
+            recall_tested: vec![1, 10, 20, 50, 100, 500],
+            path: None,
+            ground_truth: Vec::new(),
+        }
+    }
+}
+
+/// A grid of benchmarks to run.
+///
+/// Every combination of dataset, count, number of trees, distance, contender and
+/// number of chunks is indexed once, and every search scenario is then evaluated on it.
+///
+/// ```no_run
+/// use benchmarks::scenarios::Dataset;
+/// use benchmarks::BenchmarkPlan;
+///
+/// let results = BenchmarkPlan::new()
+///     .dataset(Dataset::Wikipedia)
+///     .counts([1024, 2048])
+///     .nb_trees([1, 8, 32])
+///     .results()
+///     .unwrap();
+/// for result in results {
+///     println!("{} trees => {:?}", result.nb_trees, result.recall_score());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BenchmarkPlan {
+    datasets: Vec<Arc<dyn VectorDataset>>,
+    counts: Vec<usize>,
+    nb_trees: Vec<usize>,
+    contenders: Vec<ScenarioContender>,
+    distances: Vec<ScenarioDistance>,
+    over_samplings: Vec<ScenarioOversampling>,
+    filterings: Vec<ScenarioFiltering>,
+    attribute_filter: Option<AttributeFilter>,
+    number_of_chunks: Vec<usize>,
+    queries: QuerySpec,
+    query_vectors: Option<Arc<dyn VectorDataset>>,
+    ground_truth_files: Vec<GroundTruthFile>,
+    rng_seed: u64,
+    arroy_seed: u64,
+    sleep_between_chunks: usize,
+    memory: usize,
+    ground_truth_memory: usize,
+    verbose: bool,
+}
+
+impl Default for BenchmarkPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BenchmarkPlan {
+    /// Creates an empty plan that uses the same defaults as the CLI.
+    pub fn new() -> Self {
+        BenchmarkPlan {
+            datasets: Vec::new(),
+            counts: Vec::new(),
+            nb_trees: Vec::new(),
+            contenders: vec![ScenarioContender::Arroy],
+            distances: vec![ScenarioDistance::Cosine],
+            over_samplings: vec![ScenarioOversampling::X1],
+            filterings: vec![ScenarioFiltering::NoFilter],
+            attribute_filter: None,
+            number_of_chunks: vec![1],
+            queries: QuerySpec::default(),
+            query_vectors: None,
+            ground_truth_files: Vec::new(),
+            rng_seed: RNG_SEED,
+            arroy_seed: ARROY_SEED,
+            sleep_between_chunks: 0,
+            memory: usize::MAX,
+            ground_truth_memory: usize::MAX,
+            verbose: false,
+        }
+    }
+
+    /// Adds a dataset to benchmark.
+    pub fn dataset(mut self, dataset: impl Into<Arc<dyn VectorDataset>>) -> Self {
+        self.datasets.push(dataset.into());
+        self
+    }
+
+    /// Adds multiple datasets to benchmark.
+    pub fn datasets<D: Into<Arc<dyn VectorDataset>>>(
+        mut self,
+        datasets: impl IntoIterator<Item = D>,
+    ) -> Self {
+        self.datasets.extend(datasets.into_iter().map(Into::into));
+        self
+    }
+
+    /// The different number of documents to evaluate from each dataset.
+    pub fn counts(mut self, counts: impl IntoIterator<Item = usize>) -> Self {
+        self.counts = counts.into_iter().collect();
+        self
+    }
+
+    /// The different number of trees to generate for each number of documents.
+    pub fn nb_trees(mut self, nb_trees: impl IntoIterator<Item = usize>) -> Self {
+        self.nb_trees = nb_trees.into_iter().collect();
+        self
+    }
+
+    pub fn contenders(mut self, contenders: impl IntoIterator<Item = ScenarioContender>) -> Self {
+        self.contenders = contenders.into_iter().collect();
+        self
+    }
+
+    pub fn distances(mut self, distances: impl IntoIterator<Item = ScenarioDistance>) -> Self {
+        self.distances = distances.into_iter().collect();
+        self
+    }
+
+    pub fn over_samplings(
+        mut self,
+        over_samplings: impl IntoIterator<Item = ScenarioOversampling>,
+    ) -> Self {
+        self.over_samplings = over_samplings.into_iter().collect();
+        self
+    }
+
+    pub fn filterings(mut self, filterings: impl IntoIterator<Item = ScenarioFiltering>) -> Self {
+        self.filterings = filterings.into_iter().collect();
+        self
+    }
+
+    /// The filter selecting the candidates of the [`ScenarioFiltering::Attributes`] scenario,
+    /// applied to the attributes file next to every dataset.
+    pub fn attribute_filter(mut self, filter: AttributeFilter) -> Self {
+        self.attribute_filter = Some(filter);
+        self
+    }
+
+    /// The numbers of chunks the dataset will be split into for indexing.
+    pub fn number_of_chunks(mut self, number_of_chunks: impl IntoIterator<Item = usize>) -> Self {
+        self.number_of_chunks = number_of_chunks.into_iter().collect();
+        self
+    }
+
+    pub fn queries(mut self, queries: QuerySpec) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Searches the vectors of this dataset instead of vectors picked among the indexed ones.
+    pub fn query_vectors(mut self, queries: impl Into<Arc<dyn VectorDataset>>) -> Self {
+        self.query_vectors = Some(queries.into());
+        self
+    }
+
+    /// Uses the neighbours of the imported queries read from this file instead of
+    /// computing them, for the count and distance the file covers.
+    pub fn ground_truth(mut self, file: GroundTruthFile) -> Self {
+        self.ground_truth_files.push(file);
+        self
+    }
+
+    /// The seed used to pick the queries, defaults to [`RNG_SEED`].
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = seed;
+        self
+    }
+
+    /// The seed used by arroy to generate the trees, defaults to [`ARROY_SEED`].
+    pub fn arroy_seed(mut self, seed: u64) -> Self {
+        self.arroy_seed = seed;
+        self
+    }
+
+    /// The time to sleep between each chunk indexing specified in seconds.
+    pub fn sleep_between_chunks(mut self, seconds: usize) -> Self {
+        self.sleep_between_chunks = seconds;
+        self
+    }
+
+    /// Memory available for indexing in bytes.
+    pub fn memory(mut self, memory: usize) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Memory available to compute the exact nearest neighbours of the queries, in bytes.
+    ///
+    /// The vectors are streamed in blocks fitting in this memory instead of being loaded
+    /// all at once, which makes the counts larger than the RAM evaluable.
+    pub fn ground_truth_memory(mut self, memory: usize) -> Self {
+        self.ground_truth_memory = memory;
+        self
+    }
+
+    /// Report the progress of the indexing through `tracing`.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn query_spec(&self) -> &QuerySpec {
+        &self.queries
+    }
+
+    /// The datasets to benchmark, once truncated and transformed.
+    pub fn opened_datasets(&self) -> &[Arc<dyn VectorDataset>] {
+        &self.datasets
+    }
+
+    /// The CSV header matching the lines generated by [`Self::csv_line`].
+    ///
+    /// The dataset, contender, distance, oversampling, filtering and number of chunks columns
+    /// are only added when the plan benchmarks more than one of them, the contender column
+    /// being followed by the version of the contender, the dimensions column
+    /// when the datasets don't all have the same number of dimensions or were truncated or
+    /// transformed to fewer dimensions than their file, and the transforms column
+    /// when the vectors were transformed. The size of the database is written after every chunk.
+    pub fn csv_header(&self) -> String {
+        let mut header = String::new();
+        if self.datasets.len() > 1 {
+            header.push_str("dataset,");
+        }
+        if self.contenders.len() > 1 {
+            header.push_str("contender,contender version,");
+        }
+        if self.distances.len() > 1 {
+            header.push_str("distance,");
+        }
+        if self.has_effective_dimensions() {
+            header.push_str("dimensions,");
+        }
+        if self.has_transforms() {
+            header.push_str("transforms,");
+        }
+        if self.over_samplings.len() > 1 {
+            header.push_str("oversampling,");
+        }
+        if self.filterings.len() > 1 {
+            header.push_str("filtering,");
+        }
+        header.push_str("nb vectors,nb trees,");
+        if self.number_of_chunks.len() > 1 {
+            header.push_str("nb chunks,");
+        }
+        match self.max_number_of_chunks() {
+            0 | 1 => header.push_str("db size in bytes,"),
+            chunks => {
+                for chunk in 1..=chunks {
+                    write!(&mut header, "db size in bytes after chunk {chunk},").unwrap();
+                }
+            }
+        }
+        header.push_str("recall score,");
+        for recall in &self.queries.recall_tested {
+            write!(&mut header, "recall@{recall},").unwrap();
+        }
+        header.push_str("indexing time in ms,search time in ms");
+        header
+    }
+
+    /// The lines of the CSV describing the result, one per search scenario.
+    pub fn csv_line(&self, result: &BenchmarkResult) -> String {
+        let mut lines = Vec::new();
+        for search in &result.searches {
+            let mut line = String::new();
+            if self.datasets.len() > 1 {
+                write!(&mut line, "{},", result.dataset).unwrap();
+            }
+            if self.contenders.len() > 1 {
+                let version = result.contender.version().unwrap_or("unknown").replace(',', " ");
+                write!(&mut line, "{},{version},", result.contender).unwrap();
+            }
+            if self.distances.len() > 1 {
+                write!(&mut line, "{},", result.distance).unwrap();
+            }
+            if self.has_effective_dimensions() {
+                write!(&mut line, "{},", result.dimensions).unwrap();
+            }
+            if self.has_transforms() {
+                write!(&mut line, "{},", result.transforms.iter().join("+")).unwrap();
+            }
+            if self.over_samplings.len() > 1 {
+                write!(&mut line, "{},", search.search.oversampling).unwrap();
+            }
+            if self.filterings.len() > 1 {
+                write!(&mut line, "{},", search.search.filtering).unwrap();
+            }
+            write!(&mut line, "{},{},", result.count, result.nb_trees).unwrap();
+            if self.number_of_chunks.len() > 1 {
+                write!(&mut line, "{},", result.number_of_chunks).unwrap();
+            }
+            let sizes = result.indexing.database_sizes();
+            for chunk in 0..self.max_number_of_chunks().max(1) {
+                match sizes.get(chunk) {
+                    Some(size) => write!(&mut line, "{size},").unwrap(),
+                    None => line.push(','),
+                }
+            }
+            write!(&mut line, "{:#.2},", search.recall_score().0).unwrap();
+            for (_, recall) in &search.recalls {
+                write!(&mut line, "{:#.2},", recall.0).unwrap();
+            }
+            write!(
+                &mut line,
+                "{:.2},{:.2}",
+                result.indexing.total_duration().as_secs_f64() * 1000.0,
+                search.time_to_search.as_secs_f64() * 1000.0
+            )
+            .unwrap();
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
+    fn max_number_of_chunks(&self) -> usize {
+        self.number_of_chunks.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Whether the number of dimensions of the indexed vectors must be written because
+    /// it varies or because the vectors were truncated or transformed to fewer dimensions.
+    fn has_effective_dimensions(&self) -> bool {
+        self.datasets.iter().map(|dataset| dataset.dimensions()).dedup().count() > 1
+            || self.datasets.iter().any(|dataset| dataset.dimensions() != dataset.file_dimensions())
+    }
+
+    fn has_transforms(&self) -> bool {
+        self.datasets.iter().any(|dataset| !dataset.transforms().is_empty())
+    }
+
+    /// Checks that the plan can run, [`Self::run`] also does it before running anything.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(!self.datasets.is_empty(), "Must specify at least one dataset");
+        ensure!(!self.nb_trees.is_empty(), "Must specify at least one number of trees");
+        ensure!(!self.counts.is_empty(), "Must specify at least one number of vectors");
+        ensure!(!self.number_of_chunks.is_empty(), "Must specify at least one number of chunks");
+        ensure!(!self.number_of_chunks.contains(&0), "Cannot split the dataset in zero chunks");
+        if self.contenders.contains(&ScenarioContender::Qdrant) {
+            bail!("Qdrant is not supported yet");
+        }
+        if cfg!(not(feature = "arroy-0-5")) && self.contenders.contains(&ScenarioContender::Arroy05)
+        {
+            bail!("The benchmarks must be compiled with `--features arroy-0-5` to use arroy 0.5");
+        }
+        ensure!(
+            !self.filterings.contains(&ScenarioFiltering::Attributes)
+                || self.attribute_filter.is_some(),
+            "Must specify an attribute filter to filter the documents by their attributes"
+        );
+        self.validate_queries()
+    }
+
+    fn validate_queries(&self) -> anyhow::Result<()> {
+        if let Some(queries) = &self.query_vectors {
+            for dataset in &self.datasets {
+                ensure!(
+                    queries.dimensions() == dataset.dimensions(),
+                    "The queries have {} dimensions but the vectors of {} have {}",
+                    queries.dimensions(),
+                    dataset.name(),
+                    dataset.dimensions()
+                );
+                ensure!(
+                    dataset.transforms().is_empty(),
+                    "The imported queries cannot be transformed like the vectors of {}",
+                    dataset.name()
+                );
+            }
+        }
+        if self.ground_truth_files.is_empty() {
+            return Ok(());
+        }
+
+        let Some(queries) = &self.query_vectors else {
+            bail!("Must specify the queries whose nearest neighbours are imported");
+        };
+        ensure!(
+            self.datasets.len() == 1,
+            "Cannot import the ground truth of more than one dataset"
+        );
+        ensure!(
+            self.filterings.iter().all(|&filtering| filtering == ScenarioFiltering::NoFilter),
+            "The imported ground truth only contains the nearest neighbours without filter"
+        );
+        let max = self.queries.recall_tested.iter().max().copied().unwrap_or_default();
+        for file in &self.ground_truth_files {
+            let path = file.path.display();
+            ensure!(
+                file.neighbours.len() == queries.len(),
+                "{path} contains the neighbours of {} queries but there are {} queries",
+                file.neighbours.len(),
+                queries.len()
+            );
+            ensure!(
+                file.nb_neighbours() >= max,
+                "{path} contains {} neighbours per query but the recall@{max} is tested",
+                file.nb_neighbours()
+            );
+            if let Some(distance) = file.distance {
+                ensure!(
+                    self.distances.contains(&distance),
+                    "{path} was computed with the {distance:?} distance which is not benchmarked"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the whole plan and collects every result.
+    pub fn results(&self) -> anyhow::Result<Vec<BenchmarkResult>> {
+        let mut results = Vec::new();
+        self.run(|result| results.push(result))?;
+        Ok(results)
+    }
+
+    /// Runs the whole plan and calls `callback` as soon as a result is available.
+    pub fn run(&self, mut callback: impl FnMut(BenchmarkResult)) -> anyhow::Result<()> {
+        self.validate()?;
+
+        let search: Vec<_> = iproduct!(&self.over_samplings, &self.filterings)
+            .map(|(&oversampling, &filtering)| ScenarioSearch { oversampling, filtering })
+            .sorted()
+            .dedup()
+            .collect();
+        let search: Vec<&ScenarioSearch> = search.iter().collect();
+        let imported = self.imported_queries();
+
+        for (dataset, distance) in iproduct!(&self.datasets, &self.distances) {
+            self.check_ground_truth_checksums(dataset.as_ref())?;
+            let matching = match &self.attribute_filter {
+                Some(filter) if self.filterings.contains(&ScenarioFiltering::Attributes) => {
+                    Some(filter.matching_documents(dataset.as_ref())?)
+                }
+                _ => None,
+            };
+            for &count in &self.counts {
+                let count = count.min(dataset.len());
+                let matching = matching.as_ref().map(|matching| {
+                    let mut matching = matching.clone();
+                    matching.remove_range(count as u32..);
+                    matching
+                });
+                let targets = self.targets(dataset.as_ref(), count, imported.as_deref());
+                let ground_truth = self.ground_truth_file(dataset.as_ref(), *distance, count);
+                // The queries and their expected answers are shared by every contender.
+                let queries = match distance {
+                    ScenarioDistance::Cosine => self.generate_queries::<Cosine>(
+                        dataset.as_ref(),
+                        count,
+                        &targets,
+                        &search,
+                        matching.as_ref(),
+                        ground_truth,
+                    )?,
+                };
+
+                for (&nb_trees, &contender, &number_of_chunks) in
+                    iproduct!(&self.nb_trees, &self.contenders, &self.number_of_chunks)
+                {
+                    let (indexing, searches) = self.index_and_search(
+                        contender,
+                        *distance,
+                        dataset.as_ref(),
+                        count,
+                        nb_trees,
+                        number_of_chunks,
+                        &search,
+                        &queries,
+                    );
+
+                    (callback)(BenchmarkResult {
+                        dataset: dataset.name().to_string(),
+                        dimensions: dataset.dimensions(),
+                        transforms: dataset.transforms().to_vec(),
+                        contender,
+                        distance: *distance,
+                        count,
+                        nb_trees,
+                        number_of_chunks,
+                        indexing,
+                        searches,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn index_and_search(
+        &self,
+        contender: ScenarioContender,
+        distance: ScenarioDistance,
+        dataset: &dyn VectorDataset,
+        count: usize,
+        nb_trees: usize,
+        number_of_chunks: usize,
+        search: &[&ScenarioSearch],
+        queries: &[(
+            Option<u32>,
+            &[f32],
+            HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>,
+        )],
+    ) -> (IndexingMetrics, Vec<SearchResult>) {
+        match (contender, distance) {
+            (ScenarioContender::Qdrant, _) => unreachable!(),
+            (ScenarioContender::Arroy, ScenarioDistance::Cosine) => {
+                arroy_bench::prepare_and_run::<Cosine, _, _>(
+                    dataset,
+                    count,
+                    Some(nb_trees),
+                    number_of_chunks,
+                    self.sleep_between_chunks,
+                    self.memory,
+                    self.arroy_seed,
+                    self.verbose,
+                    |metrics, env, database| {
+                        let searches = arroy_bench::run_scenarios(
+                            env,
+                            search,
+                            queries,
+                            &self.queries.recall_tested,
+                            database,
+                        );
+                        (metrics, searches)
+                    },
+                )
+            }
+            #[cfg(feature = "arroy-0-5")]
+            (ScenarioContender::Arroy05, ScenarioDistance::Cosine) => {
+                crate::arroy05_bench::prepare_and_run::<arroy_0_5::distances::Cosine, _, _>(
+                    dataset,
+                    count,
+                    Some(nb_trees),
+                    number_of_chunks,
+                    self.sleep_between_chunks,
+                    self.memory,
+                    self.arroy_seed,
+                    self.verbose,
+                    |metrics, env, database| {
+                        let searches = crate::arroy05_bench::run_scenarios(
+                            env,
+                            search,
+                            queries,
+                            &self.queries.recall_tested,
+                            database,
+                        );
+                        (metrics, searches)
+                    },
+                )
+            }
+            #[cfg(not(feature = "arroy-0-5"))]
+            (ScenarioContender::Arroy05, _) => unreachable!(),
+        }
+    }
+
+    /// Computes the expected answers of the queries for every filtering scenario,
+    /// `matching` are the documents matching the attribute filter. The answers are
+    /// read from the imported `ground_truth` when it covers the `count` first vectors.
+    ///
+    /// The `count` first vectors of the `dataset` are streamed to find the nearest neighbours
+    /// of the queries, see [`exact_nearest_neighbours`]. The filterings that keep a ratio of
+    /// the nearest vectors as candidates only need more of these neighbours.
+    #[allow(clippy::too_many_arguments)]
+    fn generate_queries<'v, D: crate::Distance>(
+        &self,
+        dataset: &dyn VectorDataset,
+        count: usize,
+        targets: &'v [(Option<u32>, Cow<[f32]>)],
+        search: &[&ScenarioSearch],
+        matching: Option<&RoaringBitmap>,
+        ground_truth: Option<&GroundTruthFile>,
+    ) -> anyhow::Result<
+        Vec<(
+            Option<u32>,
+            &'v [f32],
+            HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>,
+        )>,
+    > {
+        let max = self.queries.recall_tested.iter().max().copied().unwrap_or_default();
+        // If we have no recall we can skip entirely the generation of the queries
+        if max == 0 {
+            return Ok(Vec::new());
+        }
+
+        let vectors: Vec<_> = targets.iter().map(|(_, target)| &target[..]).collect();
+        if let Some(file) = ground_truth {
+            file.check::<D>(dataset, count, &vectors)?;
+            tracing::info!(
+                "Using the nearest neighbours of {} for {count} vectors",
+                file.path.display(),
+            );
+            return Ok(targets
+                .iter()
+                .zip(&file.neighbours)
+                .map(|((id, target), neighbours)| {
+                    let answer = (None, neighbours[..max].to_vec());
+                    (*id, &target[..], HashMap::from([(ScenarioFiltering::NoFilter, answer)]))
+                })
+                .collect());
+        }
+
+        // The candidates of the filterings keeping a ratio of the nearest vectors are the first
+        // neighbours without filter, they are selected in a separate pass over the vectors.
+        let ratio_len = |filtering: ScenarioFiltering| match filtering {
+            ScenarioFiltering::NoFilter | ScenarioFiltering::Attributes => None,
+            filtering => filtering.to_ratio_f32().map(|ratio| (count as f32 * ratio) as usize),
+        };
+        let filterings: Vec<_> = search.iter().map(|s| s.filtering).unique().collect();
+        let lens: Vec<_> = filterings.iter().filter_map(|&f| ratio_len(f)).collect();
+        let attributes = filterings.contains(&ScenarioFiltering::Attributes);
+        let nearest = if filterings.iter().any(|&f| f != ScenarioFiltering::Attributes) {
+            exact_nearest_neighbours::<D>(
+                dataset,
+                count,
+                &vectors,
+                max,
+                None,
+                self.ground_truth_memory,
+            )?
+        } else {
+            Vec::new()
+        };
+        let ratio_candidates = if lens.is_empty() {
+            vec![Vec::new(); vectors.len()]
+        } else {
+            nearest_candidates::<D>(dataset, count, &vectors, &lens, self.ground_truth_memory)?
+        };
+        let matching_nearest = if attributes {
+            exact_nearest_neighbours::<D>(
+                dataset,
+                count,
+                &vectors,
+                max,
+                matching,
+                self.ground_truth_memory,
+            )?
+        } else {
+            Vec::new()
+        };
+
+        let ids = |neighbours: &[(u32, f32)], len: usize| -> Vec<u32> {
+            neighbours.iter().take(len).map(|&(id, _)| id).collect()
+        };
+        Ok(targets
+            .iter()
+            .zip(ratio_candidates)
+            .enumerate()
+            .map(|(i, ((id, target), ratio_candidates))| {
+                // We collect the different filtered versions here, the candidates of
+                // the ratios are in the same order as the filterings.
+                let mut ratio_candidates = ratio_candidates.into_iter();
+                let filtered: HashMap<_, _> = filterings
+                    .iter()
+                    .map(|&filtering| {
+                        let answer = match filtering {
+                            ScenarioFiltering::NoFilter => (None, ids(&nearest[i], max)),
+                            ScenarioFiltering::Attributes => {
+                                (matching.cloned(), ids(&matching_nearest[i], max))
+                            }
+                            filtering => {
+                                let len = ratio_len(filtering).unwrap();
+                                let candidates = ratio_candidates.next().unwrap();
+                                // This is the real expected answer without the filtered out candidates.
+                                (Some(candidates), ids(&nearest[i], len.min(max)))
+                            }
+                        };
+                        (filtering, answer)
+                    })
+                    .collect();
+
+                (*id, &target[..], filtered)
+            })
+            .collect())
+    }
+
+    /// Reads the vectors of the imported queries, if any.
+    fn imported_queries(&self) -> Option<Vec<Cow<'_, [f32]>>> {
+        let queries = self.query_vectors.as_ref()?;
+        Some((0..queries.len()).map(|i| queries.vector(i).unwrap()).collect())
+    }
+
+    /// The imported queries or the queries picked among the `count` first vectors of the dataset,
+    /// with their id in the latter case.
+    fn targets<'v>(
+        &self,
+        dataset: &'v dyn VectorDataset,
+        count: usize,
+        imported: Option<&'v [Cow<[f32]>]>,
+    ) -> Vec<(Option<u32>, Cow<'v, [f32]>)> {
+        match imported {
+            Some(queries) => {
+                queries.iter().map(|query| (None, Cow::Borrowed(&query[..]))).collect()
+            }
+            None => self
+                .query_ids(count)
+                .into_iter()
+                .map(|id| (Some(id), dataset.vector(id as usize).unwrap()))
+                .collect(),
+        }
+    }
+
+    /// Picks the ids of the queries among the `count` first vectors,
+    /// the same ones for every contender and run.
+    fn query_ids(&self, count: usize) -> Vec<u32> {
+        let mut rng = StdRng::seed_from_u64(self.rng_seed);
+        (0..self.queries.nb_queries).map(|_| (0..count as u32).choose(&mut rng).unwrap()).collect()
+    }
+
+    /// The imported ground truth of the `count` first vectors of the dataset for this distance.
+    fn ground_truth_file(
+        &self,
+        dataset: &dyn VectorDataset,
+        distance: ScenarioDistance,
+        count: usize,
+    ) -> Option<&GroundTruthFile> {
+        self.ground_truth_files.iter().find(|file| {
+            file.count.unwrap_or(dataset.len()) == count
+                && file.distance.is_none_or(|d| d == distance)
+        })
+    }
+
+    /// Refuses the imported ground truths that were computed on another dataset.
+    fn check_ground_truth_checksums(&self, dataset: &dyn VectorDataset) -> anyhow::Result<()> {
+        let files: Vec<_> = self
+            .ground_truth_files
+            .iter()
+            .filter_map(|file| Some((file, file.checksum?)))
+            .collect();
+        if files.is_empty() {
+            return Ok(());
+        }
+        let checksum = dataset.checksum();
+        for (file, expected) in files {
+            ensure!(
+                expected == checksum,
+                "{} was computed on another dataset than {}",
+                file.path.display(),
+                dataset.name()
+            );
+        }
+        Ok(())
+    }
+
+    /// Computes the exact nearest neighbours of the queries of every dataset, distance and
+    /// count, the answers the benchmarks are evaluated against without filter, and calls
+    /// `callback` as soon as they are available. As many neighbours as the largest recall
+    /// tested are computed.
+    pub fn ground_truths(
+        &self,
+        mut callback: impl FnMut(GroundTruth) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let max = self.queries.recall_tested.iter().max().copied().unwrap_or_default();
+        ensure!(max != 0, "Must test at least one recall to compute the nearest neighbours");
+        self.validate_queries()?;
+        let imported = self.imported_queries();
+
+        for (dataset, distance) in iproduct!(&self.datasets, &self.distances) {
+            let checksum = dataset.checksum();
+            for &count in &self.counts {
+                // The vectors are streamed, only the queries are kept in memory.
+                let count = count.min(dataset.len());
+                let (query_ids, queries): (_, Vec<_>) = match &imported {
+                    Some(queries) => (Vec::new(), queries.iter().map(|q| q.to_vec()).collect()),
+                    None => {
+                        let ids = self.query_ids(count);
+                        let queries = ids
+                            .iter()
+                            .map(|&id| dataset.vector(id as usize).unwrap().into_owned())
+                            .collect();
+                        (ids, queries)
+                    }
+                };
+                let vectors: Vec<_> = queries.iter().map(|query| &query[..]).collect();
+                let nearest = match distance {
+                    ScenarioDistance::Cosine => exact_nearest_neighbours::<Cosine>(
+                        dataset.as_ref(),
+                        count,
+                        &vectors,
+                        max,
+                        None,
+                        self.ground_truth_memory,
+                    )?,
+                };
+
+                (callback)(GroundTruth {
+                    dataset: dataset.name().to_string(),
+                    distance: *distance,
+                    dimensions: dataset.dimensions(),
+                    count,
+                    checksum,
+                    seed: self.rng_seed,
+                    query_ids,
+                    queries,
+                    neighbours: nearest
+                        .iter()
+                        .map(|n| n.iter().map(|&(id, _)| id).collect())
+                        .collect(),
+                    distances: nearest
+                        .iter()
+                        .map(|n| n.iter().map(|&(_, d)| d).collect())
+                        .collect(),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The recalls measured for one search scenario.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub search: ScenarioSearch,
+    /// The recall measured for each number of fetched documents.
+    pub recalls: Vec<(usize, Recall)>,
+    pub time_to_search: Duration,
+}
+
+/// The outcome of indexing a number of vectors with a number of trees
+/// and evaluating every search scenario on it.
+#[derive(Debug)]
+pub struct BenchmarkResult {
+    pub dataset: String,
+    /// The number of dimensions of the indexed vectors, smaller than the one of
+    /// the file when the vectors were truncated.
+    pub dimensions: usize,
+    /// The transforms applied to the vectors before indexing them.
+    pub transforms: Vec<Transform>,
+    pub contender: ScenarioContender,
+    pub distance: ScenarioDistance,
+    /// The number of vectors that were indexed.
+    pub count: usize,
+    /// The number of trees that was requested.
+    pub nb_trees: usize,
+    pub number_of_chunks: usize,
+    pub indexing: IndexingMetrics,
+    pub searches: Vec<SearchResult>,
+}
+
+impl SearchResult {
+    /// The average of every recall measured in this search scenario.
+    pub fn recall_score(&self) -> Recall {
+        let recalls = self.recalls.iter().map(|(_, r)| r.0);
+        let (sum, len) = recalls.fold((0.0, 0), |(sum, len), r| (sum + r, len + 1));
+        Recall(sum / len as f32)
+    }
+}
+
+impl BenchmarkResult {
+    /// The average of every recall measured on this index.
+    pub fn recall_score(&self) -> Recall {
+        let recalls = self.searches.iter().flat_map(|s| &s.recalls).map(|(_, r)| r.0);
+        let (sum, len) = recalls.fold((0.0, 0), |(sum, len), r| (sum + r, len + 1));
+        Recall(sum / len as f32)
+    }
+
+    /// The time spent searching in every search scenario.
+    pub fn time_to_search(&self) -> Duration {
+        self.searches.iter().map(|s| s.time_to_search).sum()
+    }
+}
@@ -25,7 +25,11 @@ pub fn run_scenarios<D: Distance>(
     _time_to_index: Duration,
     _distance: &ScenarioDistance,
     search: Vec<&ScenarioSearch>,
-    _queries: Vec<(&u32, &&[f32], HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>)>,
+    _queries: Vec<(
+        Option<u32>,
+        &[f32],
+        HashMap<ScenarioFiltering, (Option<RoaringBitmap>, Vec<u32>)>,
+    )>,
     _database: arroy::Database<D>,
 ) {
     // let database_size =
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::scenarios::Dataset;
+use crate::{open_dataset, SyntheticDataset, SyntheticSpec, VectorDataset};
+
+/// The registry loaded when none is specified, if it exists.
+pub const DEFAULT_REGISTRY_PATH: &str = "datasets.toml";
+
+/// A list of named dataset files that can be benchmarked without patching the crate.
+///
+/// ```toml
+/// [datasets.movies-minilm]
+/// path = "assets/movies-minilm.mat"
+/// description = "The overview of 45k movies embedded with all-MiniLM-L6-v2"
+/// url = "https://www.kaggle.com/datasets/rounakbanik/the-movies-dataset"
+///
+/// [datasets.legacy-dump]
+/// path = "assets/legacy-dump.mat"
+/// # Only required by the files generated without header
+/// dimensions = 768
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetRegistry {
+    #[serde(default)]
+    pub datasets: BTreeMap<String, RegisteredDataset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RegisteredDataset {
+    pub path: PathBuf,
+    /// The number of dimensions, only required by the files without header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Where the dataset comes from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl DatasetRegistry {
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<DatasetRegistry> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("while reading {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("while parsing {}", path.display()))
+    }
+
+    /// Loads the registry at `path`, or the [`DEFAULT_REGISTRY_PATH`] if it exists.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<DatasetRegistry> {
+        match path {
+            Some(path) => DatasetRegistry::from_path(path),
+            None if Path::new(DEFAULT_REGISTRY_PATH).exists() => {
+                DatasetRegistry::from_path(DEFAULT_REGISTRY_PATH)
+            }
+            None => Ok(DatasetRegistry::default()),
+        }
+    }
+
+    /// Returns the file corresponding to the name of a registered dataset.
+    pub fn get(&self, name: &str) -> Option<DatasetFile> {
+        self.datasets.get(name).map(|dataset| DatasetFile {
+            name: Some(name.to_string()),
+            path: dataset.path.clone(),
+            dimensions: dataset.dimensions,
+        })
+    }
+}
+
+/// A dataset file that is not part of the [`Dataset`] enum, in any format supported by [`open_dataset`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DatasetFile {
+    /// The name to show in the results, the one of the header or the file name by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// The number of dimensions, only required by the files without header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+}
+
+impl DatasetFile {
+    /// Opens the file according to its extension, see [`open_dataset`].
+    pub fn open(&self) -> anyhow::Result<Arc<dyn VectorDataset>> {
+        open_dataset(&self.path, self.name.as_deref(), self.dimensions)
+    }
+}
+
+/// A dataset to benchmark: a built-in one, a file, a synthetic one or the name
+/// of a dataset of the [`DatasetRegistry`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DatasetSource {
+    Builtin(Dataset),
+    File(DatasetFile),
+    Synthetic(SyntheticSpec),
+    Registered(String),
+}
+
+impl DatasetSource {
+    /// Replaces the name of a registered dataset by its file.
+    pub fn resolve(self, registry: &DatasetRegistry) -> anyhow::Result<DatasetSource> {
+        match self {
+            DatasetSource::Registered(name) => match registry.get(&name) {
+                Some(file) => Ok(DatasetSource::File(file)),
+                None => bail!(
+                    "Unknown dataset `{name}`, it is neither a built-in dataset nor in the registry"
+                ),
+            },
+            source => Ok(source),
+        }
+    }
+
+    /// Opens the dataset, the `dimensions` are used for the files without header
+    /// that don't specify their own.
+    pub fn open(&self, dimensions: Option<usize>) -> anyhow::Result<Arc<dyn VectorDataset>> {
+        match self {
+            DatasetSource::Builtin(dataset) => dataset.open(dimensions),
+            DatasetSource::File(file) => {
+                DatasetFile { dimensions: file.dimensions.or(dimensions), ..file.clone() }.open()
+            }
+            DatasetSource::Synthetic(spec) => Ok(Arc::new(SyntheticDataset::new(spec.clone())?)),
+            DatasetSource::Registered(name) => {
+                bail!("The dataset `{name}` must be resolved with the registry before being opened")
+            }
+        }
+    }
+}
+
+impl From<Dataset> for DatasetSource {
+    fn from(dataset: Dataset) -> Self {
+        DatasetSource::Builtin(dataset)
+    }
+}
+
+impl FromStr for DatasetSource {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match Dataset::from_str(s, true) {
+            Ok(dataset) => DatasetSource::Builtin(dataset),
+            Err(_) => DatasetSource::Registered(s.to_string()),
+        })
+    }
+}
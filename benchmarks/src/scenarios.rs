@@ -1,12 +1,18 @@
 use std::fmt;
+use std::fs::File;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 
+use anyhow::Context;
 use clap::ValueEnum;
 use enum_iterator::Sequence;
+use matfile::MatHeader;
+use serde::{Deserialize, Serialize};
 
-use crate::MatLEView;
+use crate::{open_dataset, MatLEView, VectorDataset};
 
-#[derive(Debug, Copy, Clone, ValueEnum, Sequence)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Sequence, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Dataset {
     /// Hackernews posts (512)
     HnPosts,
@@ -22,48 +28,127 @@ pub enum Dataset {
     DbPedia3Large,
 }
 
+impl Dataset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dataset::HnPosts => "Hackernews posts",
+            Dataset::DatacompSmall => "Datacomp small",
+            Dataset::Wikipedia => "wikipedia 22 12 simple embeddings",
+            Dataset::HnTopPost => "Hackernews top posts",
+            Dataset::DbPediaAda002 => "db pedia OpenAI text-embedding ada  002",
+            Dataset::DbPedia3Large => "db pedia OpenAI text-embedding 3 large",
+        }
+    }
+
+    pub fn path(self) -> &'static str {
+        match self {
+            Dataset::HnPosts => "assets/hn-posts.mat",
+            Dataset::DatacompSmall => "assets/datacomp-small.mat",
+            Dataset::Wikipedia => "assets/wikipedia-22-12-simple-embeddings.mat",
+            Dataset::HnTopPost => "assets/hn-top-posts.mat",
+            Dataset::DbPediaAda002 => "assets/db-pedia-OpenAI-text-embedding-ada-002.mat",
+            Dataset::DbPedia3Large => "assets/db-pedia-OpenAI-text-embedding-3-large.mat",
+        }
+    }
+
+    /// The number of dimensions of the files generated before the `.mat` header existed.
+    pub fn legacy_dimensions(self) -> usize {
+        match self {
+            Dataset::HnPosts => 512,
+            Dataset::DatacompSmall => 768,
+            Dataset::Wikipedia => 768,
+            Dataset::HnTopPost => 1024,
+            Dataset::DbPediaAda002 => 1536,
+            Dataset::DbPedia3Large => 3072,
+        }
+    }
+
+    /// Opens the dataset, the `dimensions` are only required by the files without header
+    /// and default to the [`Self::legacy_dimensions`]. When specified for a file with
+    /// a header, they must match the ones of the header.
+    ///
+    /// The file can store `f32`, `f16` or `bf16` values, they are converted to `f32` on access.
+    pub fn open(self, dimensions: Option<usize>) -> anyhow::Result<Arc<dyn VectorDataset>> {
+        open_dataset(self.path(), Some(self.name()), self.dimensions(dimensions)?)
+    }
+
+    fn dimensions(self, dimensions: Option<usize>) -> anyhow::Result<Option<usize>> {
+        let file =
+            File::open(self.path()).with_context(|| format!("while opening {}", self.path()))?;
+        Ok(match MatHeader::read_from(file)? {
+            Some(_) => dimensions,
+            None => Some(dimensions.unwrap_or(self.legacy_dimensions())),
+        })
+    }
+}
+
 impl From<Dataset> for MatLEView<f32> {
     fn from(dataset: Dataset) -> Self {
-        match dataset {
-            Dataset::HnPosts => MatLEView::new("Hackernews posts", "assets/hn-posts.mat", 512),
-            Dataset::DatacompSmall => {
-                MatLEView::new("Datacomp small", "assets/datacomp-small.mat", 768)
-            }
-            Dataset::Wikipedia => MatLEView::new(
-                "wikipedia 22 12 simple embeddings",
-                "assets/wikipedia-22-12-simple-embeddings.mat",
-                768,
-            ),
-            Dataset::HnTopPost => {
-                MatLEView::new("Hackernews top posts", "assets/hn-top-posts.mat", 1024)
-            }
-            Dataset::DbPediaAda002 => MatLEView::new(
-                "db pedia OpenAI text-embedding ada  002",
-                "assets/db-pedia-OpenAI-text-embedding-ada-002.mat",
-                1536,
-            ),
-            Dataset::DbPedia3Large => MatLEView::new(
-                "db pedia OpenAI text-embedding 3 large",
-                "assets/db-pedia-OpenAI-text-embedding-3-large.mat",
-                3072,
-            ),
-        }
+        let dimensions = dataset.dimensions(None).unwrap();
+        MatLEView::open(dataset.path(), dimensions).unwrap().with_name(dataset.name())
+    }
+}
+
+impl From<Dataset> for Arc<dyn VectorDataset> {
+    fn from(dataset: Dataset) -> Self {
+        dataset.open(None).unwrap()
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum ScenarioContender {
     Qdrant,
+    /// The arroy version specified in the `Cargo.toml`
     Arroy,
+    /// arroy 0.5, requires the `arroy-0-5` feature
+    Arroy05,
     // Typesense,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence)]
+impl ScenarioContender {
+    /// The version of the crate benchmarked, `None` if it is not compiled in.
+    pub fn version(self) -> Option<&'static str> {
+        match self {
+            ScenarioContender::Qdrant => None,
+            ScenarioContender::Arroy => Some(env!("BENCHMARKS_ARROY_VERSION")),
+            ScenarioContender::Arroy05 => option_env!("BENCHMARKS_ARROY_0_5_VERSION"),
+        }
+    }
+}
+
+impl fmt::Display for ScenarioContender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioContender::Qdrant => f.write_str("qdrant"),
+            ScenarioContender::Arroy => f.write_str("arroy"),
+            ScenarioContender::Arroy05 => f.write_str("arroy 0.5"),
+        }
+    }
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum ScenarioDistance {
     Cosine,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence)]
+impl fmt::Display for ScenarioDistance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioDistance::Cosine => f.write_str("cosine"),
+        }
+    }
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Sequence, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum ScenarioOversampling {
     X1,
     X3,
@@ -87,7 +172,21 @@ impl fmt::Display for ScenarioOversampling {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum, Sequence)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    ValueEnum,
+    Sequence,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum ScenarioFiltering {
     NoFilter,
     Filter50,
@@ -98,25 +197,39 @@ pub enum ScenarioFiltering {
     Filter6,
     Filter2,
     Filter1,
+    /// The candidates are the documents matching the attribute filter
+    Attributes,
 }
 
 impl ScenarioFiltering {
-    pub fn to_ratio_f32(self) -> f32 {
+    /// The ratio of the documents kept as candidates, `None` when they are picked by their attributes.
+    pub fn to_ratio_f32(self) -> Option<f32> {
         match self {
-            ScenarioFiltering::NoFilter => 1.0,
-            ScenarioFiltering::Filter50 => 0.50,
-            ScenarioFiltering::Filter25 => 0.25,
-            ScenarioFiltering::Filter15 => 0.15,
-            ScenarioFiltering::Filter10 => 0.1,
-            ScenarioFiltering::Filter8 => 0.08,
-            ScenarioFiltering::Filter6 => 0.06,
-            ScenarioFiltering::Filter2 => 0.02,
-            ScenarioFiltering::Filter1 => 0.01,
+            ScenarioFiltering::NoFilter => Some(1.0),
+            ScenarioFiltering::Filter50 => Some(0.50),
+            ScenarioFiltering::Filter25 => Some(0.25),
+            ScenarioFiltering::Filter15 => Some(0.15),
+            ScenarioFiltering::Filter10 => Some(0.1),
+            ScenarioFiltering::Filter8 => Some(0.08),
+            ScenarioFiltering::Filter6 => Some(0.06),
+            ScenarioFiltering::Filter2 => Some(0.02),
+            ScenarioFiltering::Filter1 => Some(0.01),
+            ScenarioFiltering::Attributes => None,
+        }
+    }
+}
+
+impl fmt::Display for ScenarioFiltering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_ratio_f32() {
+            Some(1.0) => f.write_str("no filter"),
+            Some(ratio) => write!(f, "{}%", (ratio * 100.0).round()),
+            None => f.write_str("attributes"),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ScenarioSearch {
     pub oversampling: ScenarioOversampling,
     pub filtering: ScenarioFiltering,
@@ -0,0 +1,120 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::ensure;
+use matfile::ShardManifest;
+
+use crate::{open_dataset, VectorDataset};
+
+/// The shards described by a [`ShardManifest`], read as a single matrix.
+///
+/// The shards are opened with [`open_dataset`] and can be in any format it supports.
+#[derive(Debug, Clone)]
+pub struct ShardedDataset {
+    name: String,
+    path: PathBuf,
+    dimensions: usize,
+    shards: Vec<Arc<dyn VectorDataset>>,
+    /// The index of the first vector of every shard.
+    offsets: Vec<usize>,
+    len: usize,
+}
+
+impl ShardedDataset {
+    /// Opens the shards listed by the manifest at `path`.
+    ///
+    /// If specified, the `dimensions` must match the ones of the manifest.
+    pub fn open(
+        path: impl AsRef<Path>,
+        dimensions: Option<usize>,
+    ) -> anyhow::Result<ShardedDataset> {
+        let path = path.as_ref();
+        let manifest = ShardManifest::read_from_path(path)?;
+        if let Some(dimensions) = dimensions {
+            ensure!(
+                dimensions == manifest.dimensions,
+                "{} has {} dimensions but {dimensions} were specified",
+                path.display(),
+                manifest.dimensions
+            );
+        }
+
+        let mut shards = Vec::with_capacity(manifest.shards.len());
+        let mut offsets = Vec::with_capacity(manifest.shards.len());
+        let mut len = 0;
+        for (shard, shard_path) in manifest.shards.iter().zip(manifest.shard_paths(path)) {
+            let dataset = open_dataset(&shard_path, None, Some(manifest.dimensions))?;
+            ensure!(
+                dataset.len() == shard.rows,
+                "{} has {} vectors but the manifest declares {}",
+                shard_path.display(),
+                dataset.len(),
+                shard.rows
+            );
+            offsets.push(len);
+            len += dataset.len();
+            shards.push(dataset);
+        }
+
+        Ok(ShardedDataset {
+            name: manifest.name,
+            path: path.to_path_buf(),
+            dimensions: manifest.dimensions,
+            shards,
+            offsets,
+            len,
+        })
+    }
+
+    /// Replaces the name written in the manifest.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn shards(&self) -> &[Arc<dyn VectorDataset>] {
+        &self.shards
+    }
+}
+
+impl VectorDataset for ShardedDataset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The manifest of the shards.
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The total size of the shards.
+    fn file_size(&self) -> usize {
+        self.shards.iter().map(|shard| shard.file_size()).sum()
+    }
+
+    /// The CRC32 of the checksums of the shards, in order.
+    fn checksum(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        for shard in &self.shards {
+            hasher.update(&shard.checksum().to_le_bytes());
+        }
+        hasher.finalize()
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        if index >= self.len {
+            return None;
+        }
+        let shard = self.offsets.partition_point(|&offset| offset <= index) - 1;
+        self.shards[shard].vector(index - self.offsets[shard])
+    }
+}
@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::ensure;
+use clap::ValueEnum;
+use matfile::{Dtype, MatHeader};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::{VectorDataset, RNG_SEED};
+
+/// The distribution the vectors of a [`SyntheticDataset`] are drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyntheticKind {
+    /// Isotropic Gaussian, N(0, I)
+    Gaussian,
+    /// Gaussian mixture, isotropic clusters around random centres
+    Mixture,
+    /// Uniformly distributed on the unit sphere
+    Sphere,
+    /// Gaussian whose variance decreases with the dimension, as 1/(j+1)
+    Anisotropic,
+    /// A random low-rank projection of a Gaussian plus an isotropic noise
+    LowRank,
+}
+
+impl fmt::Display for SyntheticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Describes a synthetic dataset, the same spec always generates the same vectors.
+///
+/// ```toml
+/// datasets = [{ synthetic = "mixture", dimensions = 4096, count = 100000, clusters = 32 }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SyntheticSpec {
+    pub synthetic: SyntheticKind,
+    pub dimensions: usize,
+    pub count: usize,
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    /// The number of clusters of a [`SyntheticKind::Mixture`].
+    #[serde(default = "default_clusters")]
+    pub clusters: usize,
+    /// The rank of a [`SyntheticKind::LowRank`].
+    #[serde(default = "default_rank")]
+    pub rank: usize,
+}
+
+fn default_seed() -> u64 {
+    RNG_SEED
+}
+
+fn default_clusters() -> usize {
+    16
+}
+
+fn default_rank() -> usize {
+    16
+}
+
+impl SyntheticSpec {
+    pub fn new(kind: SyntheticKind, dimensions: usize, count: usize) -> SyntheticSpec {
+        SyntheticSpec {
+            synthetic: kind,
+            dimensions,
+            count,
+            seed: default_seed(),
+            clusters: default_clusters(),
+            rank: default_rank(),
+        }
+    }
+
+    /// A name describing every parameter that changes the generated vectors.
+    pub fn name(&self) -> String {
+        let SyntheticSpec { synthetic, dimensions, count, seed, clusters, rank } = self;
+        let parameters = match synthetic {
+            SyntheticKind::Mixture => format!("-{clusters}clusters"),
+            SyntheticKind::LowRank => format!("-rank{rank}"),
+            _ => String::new(),
+        };
+        format!("synthetic-{synthetic}{parameters}-{dimensions}d-{count}-seed{seed}")
+    }
+}
+
+/// The standard deviation of the clusters of a mixture, the centres are drawn from N(0, I).
+const CLUSTER_STD_DEV: f32 = 0.25;
+/// The standard deviation of the noise added to the low-rank vectors.
+const LOW_RANK_NOISE: f32 = 0.1;
+
+/// A dataset whose vectors are generated on the fly.
+///
+/// Every vector is drawn from its own random generator, seeded with the seed of the
+/// dataset and its index, so that they can be accessed in any order and in parallel.
+#[derive(Debug, Clone)]
+pub struct SyntheticDataset {
+    spec: SyntheticSpec,
+    name: String,
+    path: PathBuf,
+    /// The centres of the clusters of a mixture or the projection of a low-rank dataset.
+    basis: Vec<Vec<f32>>,
+}
+
+impl SyntheticDataset {
+    pub fn new(spec: SyntheticSpec) -> anyhow::Result<SyntheticDataset> {
+        ensure!(spec.dimensions != 0, "A synthetic dataset must have at least one dimension");
+        let mut rng = StdRng::seed_from_u64(spec.seed);
+        let basis = match spec.synthetic {
+            SyntheticKind::Mixture => {
+                ensure!(spec.clusters != 0, "A mixture must have at least one cluster");
+                (0..spec.clusters).map(|_| gaussian(&mut rng, spec.dimensions)).collect()
+            }
+            SyntheticKind::LowRank => {
+                ensure!(spec.rank != 0, "A low-rank dataset must have a rank of at least one");
+                let scale = (spec.rank as f32).sqrt().recip();
+                (0..spec.rank)
+                    .map(|_| {
+                        gaussian(&mut rng, spec.dimensions).iter().map(|x| x * scale).collect()
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        let name = spec.name();
+        Ok(SyntheticDataset { path: PathBuf::from(format!("synthetic/{name}")), name, spec, basis })
+    }
+
+    pub fn spec(&self) -> &SyntheticSpec {
+        &self.spec
+    }
+
+    fn generate(&self, index: usize) -> Vec<f32> {
+        let SyntheticSpec { synthetic, dimensions, seed, .. } = self.spec;
+        let mut rng = StdRng::seed_from_u64(splitmix64(seed ^ splitmix64(index as u64)));
+        match synthetic {
+            SyntheticKind::Gaussian => gaussian(&mut rng, dimensions),
+            SyntheticKind::Mixture => {
+                let centre = &self.basis[rng.gen_range(0..self.basis.len())];
+                centre
+                    .iter()
+                    .map(|c| c + CLUSTER_STD_DEV * rng.sample::<f32, _>(StandardNormal))
+                    .collect()
+            }
+            SyntheticKind::Sphere => {
+                let vector = gaussian(&mut rng, dimensions);
+                let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                vector.iter().map(|x| x / norm).collect()
+            }
+            SyntheticKind::Anisotropic => (0..dimensions)
+                .map(|j| rng.sample::<f32, _>(StandardNormal) / ((j + 1) as f32).sqrt())
+                .collect(),
+            SyntheticKind::LowRank => {
+                let z = gaussian(&mut rng, self.basis.len());
+                let mut vector: Vec<_> = (0..dimensions)
+                    .map(|_| LOW_RANK_NOISE * rng.sample::<f32, _>(StandardNormal))
+                    .collect();
+                for (z, column) in z.iter().zip(&self.basis) {
+                    vector.iter_mut().zip(column).for_each(|(x, c)| *x += z * c);
+                }
+                vector
+            }
+        }
+    }
+
+    /// Writes the whole dataset into a `.mat` file, the vectors are generated in parallel.
+    pub fn write_into(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        const CHUNK_SIZE: usize = 16 * 1024;
+
+        let header = MatHeader::new(&self.name, Dtype::F32, self.spec.dimensions, self.spec.count);
+        header.write_into(&mut writer)?;
+        for start in (0..self.spec.count).step_by(CHUNK_SIZE) {
+            let end = (start + CHUNK_SIZE).min(self.spec.count);
+            let vectors: Vec<_> = (start..end).into_par_iter().map(|i| self.generate(i)).collect();
+            for vector in vectors {
+                writer.write_all(bytemuck::cast_slice(&vector))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl VectorDataset for SyntheticDataset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A fake path describing the dataset, nothing is read from the disk.
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dimensions(&self) -> usize {
+        self.spec.dimensions
+    }
+
+    fn len(&self) -> usize {
+        self.spec.count
+    }
+
+    /// Nothing is stored on disk.
+    fn file_size(&self) -> usize {
+        0
+    }
+
+    /// The CRC32 of the name, which contains every parameter of the dataset.
+    fn checksum(&self) -> u32 {
+        crc32fast::hash(self.name.as_bytes())
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        (index < self.spec.count).then(|| Cow::Owned(self.generate(index)))
+    }
+}
+
+fn gaussian(rng: &mut StdRng, dimensions: usize) -> Vec<f32> {
+    (0..dimensions).map(|_| rng.sample(StandardNormal)).collect()
+}
+
+/// Mixes the bits of `x`, used to derive the seed of every vector.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
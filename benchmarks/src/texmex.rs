@@ -0,0 +1,219 @@
+//! Readers and a writer for the `.fvecs`, `.bvecs` and `.ivecs` formats of the TEXMEX corpus,
+//! used by SIFT1M, GIST1M and most of the ANN benchmarks.
+//!
+//! Every vector is stored as its number of dimensions, a little-endian `i32`,
+//! followed by its values: `f32` for `.fvecs`, `u8` for `.bvecs` and `i32` for `.ivecs`.
+//! The `.ivecs` files usually contain the ground truth, the ids of the nearest
+//! neighbours of every query.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use bytemuck::{AnyBitPattern, NoUninit};
+use memmap2::Mmap;
+
+use crate::dataset::checksum;
+use crate::VectorDataset;
+
+/// A `.fvecs` file, a matrix of `f32`.
+pub type FvecsView = VecsView<f32>;
+/// A `.bvecs` file, a matrix of `u8` converted to `f32` on access.
+pub type BvecsView = VecsView<u8>;
+/// A `.ivecs` file, generally the ids of the nearest neighbours of a set of queries.
+pub type IvecsView = VecsView<i32>;
+
+const DIMENSIONS_PREFIX_LEN: usize = mem::size_of::<i32>();
+
+#[derive(Debug, Clone)]
+pub struct VecsView<T> {
+    name: String,
+    path: PathBuf,
+    mmap: Arc<Mmap>,
+    dimensions: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: AnyBitPattern> VecsView<T> {
+    /// Opens a TEXMEX file and checks that all its vectors have the same number of dimensions.
+    ///
+    /// If specified, the `dimensions` must match the ones of the file.
+    pub fn open(path: impl AsRef<Path>, dimensions: Option<usize>) -> anyhow::Result<VecsView<T>> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        ensure!(mmap.len() >= DIMENSIONS_PREFIX_LEN, "{} is empty", path.display());
+        let file_dimensions = read_dimensions(&mmap[..DIMENSIONS_PREFIX_LEN]);
+        ensure!(file_dimensions > 0, "{} declares vectors of zero dimensions", path.display());
+        if let Some(dimensions) = dimensions {
+            ensure!(
+                file_dimensions == dimensions,
+                "{} contains vectors of {file_dimensions} dimensions but {dimensions} were expected",
+                path.display(),
+            );
+        }
+
+        let record_len = DIMENSIONS_PREFIX_LEN + file_dimensions * mem::size_of::<T>();
+        ensure!(
+            mmap.len() % record_len == 0,
+            "{} is {} bytes long which is not a multiple of a vector of {file_dimensions} dimensions",
+            path.display(),
+            mmap.len(),
+        );
+        for (i, record) in mmap.chunks_exact(record_len).enumerate() {
+            let dimensions = read_dimensions(&record[..DIMENSIONS_PREFIX_LEN]);
+            ensure!(
+                dimensions == file_dimensions,
+                "the vector {i} of {} has {dimensions} dimensions instead of {file_dimensions}",
+                path.display(),
+            );
+        }
+
+        let name = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into());
+        Ok(VecsView {
+            name,
+            path: path.to_path_buf(),
+            len: mmap.len() / record_len,
+            mmap: Arc::new(mmap),
+            dimensions: file_dimensions,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Replaces the name deduced from the file name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The size of the file in bytes.
+    pub fn file_size(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Computes the CRC32 of the whole file, in parallel.
+    pub fn checksum(&self) -> u32 {
+        checksum(&self.mmap)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[T]> {
+        if index < self.len {
+            let record_len = DIMENSIONS_PREFIX_LEN + self.dimensions * mem::size_of::<T>();
+            let start = index * record_len + DIMENSIONS_PREFIX_LEN;
+            Some(bytemuck::cast_slice(&self.mmap[start..(index + 1) * record_len]))
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.len()).map(|i| self.get(i).unwrap())
+    }
+}
+
+impl IvecsView {
+    /// The ids of the nearest neighbours of the `query`, from the nearest to the farthest.
+    pub fn neighbours(&self, query: usize) -> Option<&[u32]> {
+        self.get(query).map(bytemuck::cast_slice)
+    }
+}
+
+/// Writes the vectors in the TEXMEX format, `.fvecs` for `f32` and `.ivecs` for `i32` or `u32`.
+pub fn write_vecs<T: NoUninit>(mut writer: impl Write, vectors: &[Vec<T>]) -> io::Result<()> {
+    for vector in vectors {
+        writer.write_all(&(vector.len() as i32).to_le_bytes())?;
+        writer.write_all(bytemuck::cast_slice(vector))?;
+    }
+    writer.flush()
+}
+
+fn read_dimensions(bytes: &[u8]) -> usize {
+    i32::from_le_bytes(bytes.try_into().unwrap()).max(0) as usize
+}
+
+impl VectorDataset for FvecsView {
+    fn name(&self) -> &str {
+        self.name()
+    }
+
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.file_size()
+    }
+
+    fn checksum(&self) -> u32 {
+        self.checksum()
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        self.get(index).map(Cow::Borrowed)
+    }
+}
+
+impl VectorDataset for BvecsView {
+    fn name(&self) -> &str {
+        self.name()
+    }
+
+    fn path(&self) -> &Path {
+        self.path()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.file_size()
+    }
+
+    fn checksum(&self) -> u32 {
+        self.checksum()
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        self.get(index).map(|vector| Cow::Owned(vector.iter().map(|&x| x as f32).collect()))
+    }
+}
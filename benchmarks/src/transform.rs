@@ -0,0 +1,595 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use itertools::Itertools;
+use matfile::{Dtype, MatHeader};
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rayon::iter::{
+    IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{open_dataset, VectorDataset, RNG_SEED};
+
+/// Exposes the first dimensions of every vector of a dataset, renormalized.
+///
+/// The Matryoshka embeddings, like the OpenAI `text-embedding-3` ones, are trained so that
+/// their prefixes are embeddings too. The file is left untouched, the vectors are truncated
+/// when they are read.
+#[derive(Debug, Clone)]
+pub struct TruncatedView {
+    dataset: Arc<dyn VectorDataset>,
+    name: String,
+    dimensions: usize,
+}
+
+impl TruncatedView {
+    pub fn new(
+        dataset: Arc<dyn VectorDataset>,
+        dimensions: usize,
+    ) -> anyhow::Result<TruncatedView> {
+        ensure!(
+            dimensions != 0,
+            "Cannot truncate the vectors of {} to zero dimensions",
+            dataset.name()
+        );
+        ensure!(
+            dimensions <= dataset.dimensions(),
+            "Cannot truncate the vectors of {} to {dimensions} dimensions, they only have {}",
+            dataset.name(),
+            dataset.dimensions()
+        );
+        let name = format!("{} truncated to {dimensions}d", dataset.name());
+        Ok(TruncatedView { dataset, name, dimensions })
+    }
+
+    /// The dataset whose vectors are truncated.
+    pub fn inner(&self) -> &Arc<dyn VectorDataset> {
+        &self.dataset
+    }
+}
+
+impl VectorDataset for TruncatedView {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn path(&self) -> &Path {
+        self.dataset.path()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn file_dimensions(&self) -> usize {
+        self.dataset.file_dimensions()
+    }
+
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.dataset.file_size()
+    }
+
+    /// The checksum of the truncated file.
+    fn checksum(&self) -> u32 {
+        self.dataset.checksum()
+    }
+
+    fn transforms(&self) -> &[Transform] {
+        self.dataset.transforms()
+    }
+
+    /// The prefix of the vector divided by its norm, a prefix whose norm is zero is left as is.
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        let vector = self.dataset.vector(index)?;
+        let prefix = &vector[..self.dimensions];
+        let norm = prefix.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            Some(Cow::Owned(prefix.to_vec()))
+        } else {
+            Some(Cow::Owned(prefix.iter().map(|x| x / norm).collect()))
+        }
+    }
+}
+
+/// Replaces every dataset by one view per dimension, they are left as is if `dimensions` is empty.
+pub fn truncate_datasets(
+    datasets: Vec<Arc<dyn VectorDataset>>,
+    dimensions: &[usize],
+) -> anyhow::Result<Vec<Arc<dyn VectorDataset>>> {
+    if dimensions.is_empty() {
+        return Ok(datasets);
+    }
+    let mut views = Vec::with_capacity(datasets.len() * dimensions.len());
+    for dataset in datasets {
+        for &dimensions in dimensions {
+            views.push(Arc::new(TruncatedView::new(dataset.clone(), dimensions)?) as Arc<_>);
+        }
+    }
+    Ok(views)
+}
+
+/// A transformation applied to every vector of a dataset before indexing them.
+///
+/// It is written `normalize`, `center`, `pca:<dimensions>`, `gaussian-projection:<dimensions>`
+/// or `sparse-projection:<dimensions>`, on the command line and in the experiment files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Transform {
+    /// Divides the vectors by their L2 norm.
+    Normalize,
+    /// Subtracts the mean of the vectors.
+    Center,
+    /// Centers the vectors and projects them on their principal components.
+    Pca(usize),
+    /// Projects the vectors with a dense matrix drawn from N(0, 1/dimensions).
+    GaussianProjection(usize),
+    /// Projects the vectors with the very sparse random matrix of Li, Hastie and Church.
+    SparseProjection(usize),
+}
+
+impl Transform {
+    /// The number of dimensions of the vectors once transformed.
+    pub fn output_dimensions(&self, input: usize) -> usize {
+        match *self {
+            Transform::Normalize | Transform::Center => input,
+            Transform::Pca(dimensions)
+            | Transform::GaussianProjection(dimensions)
+            | Transform::SparseProjection(dimensions) => dimensions,
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::Normalize => write!(f, "normalize"),
+            Transform::Center => write!(f, "center"),
+            Transform::Pca(dimensions) => write!(f, "pca:{dimensions}"),
+            Transform::GaussianProjection(dimensions) => {
+                write!(f, "gaussian-projection:{dimensions}")
+            }
+            Transform::SparseProjection(dimensions) => write!(f, "sparse-projection:{dimensions}"),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, dimensions) = match s.trim().split_once(':') {
+            Some((kind, dimensions)) => {
+                let dimensions = dimensions
+                    .parse()
+                    .map_err(|e| format!("invalid number of dimensions in `{s}`: {e}"))?;
+                if dimensions == 0 {
+                    return Err(format!("`{s}` must keep at least one dimension"));
+                }
+                (kind, Some(dimensions))
+            }
+            None => (s.trim(), None),
+        };
+        match (kind.to_lowercase().as_str(), dimensions) {
+            ("normalize", None) => Ok(Transform::Normalize),
+            ("center", None) => Ok(Transform::Center),
+            ("pca", Some(dimensions)) => Ok(Transform::Pca(dimensions)),
+            ("gaussian-projection", Some(dimensions)) => {
+                Ok(Transform::GaussianProjection(dimensions))
+            }
+            ("sparse-projection", Some(dimensions)) => Ok(Transform::SparseProjection(dimensions)),
+            ("pca" | "gaussian-projection" | "sparse-projection", None) => {
+                Err(format!("`{s}` requires a number of dimensions, e.g. `{s}:256`"))
+            }
+            _ => Err(format!(
+                "unknown transform `{s}`, expected `normalize`, `center`, `pca:<dimensions>`, `gaussian-projection:<dimensions>` or `sparse-projection:<dimensions>`"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Transform {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Transform> for String {
+    fn from(transform: Transform) -> Self {
+        transform.to_string()
+    }
+}
+
+/// The number of vectors randomly picked to fit the mean and the principal components.
+const FIT_SAMPLE: usize = 10_000;
+/// The number of iterations of the subspace iteration used to find the principal components.
+const PCA_ITERATIONS: usize = 32;
+
+/// A transform whose parameters were fitted on the dataset.
+#[derive(Debug, Clone)]
+enum FittedTransform {
+    Normalize,
+    Center(Vec<f32>),
+    /// Subtracts the mean, if any, and computes the dot product with every row.
+    Project {
+        mean: Option<Vec<f32>>,
+        rows: Vec<Vec<f32>>,
+    },
+    /// The non-zero values of every row of the projection.
+    SparseProject(Vec<Vec<(u32, f32)>>),
+}
+
+impl FittedTransform {
+    fn apply(&self, vector: &[f32]) -> Vec<f32> {
+        match self {
+            FittedTransform::Normalize => {
+                let norm = dot(vector, vector).sqrt();
+                if norm == 0.0 {
+                    vector.to_vec()
+                } else {
+                    vector.iter().map(|x| x / norm).collect()
+                }
+            }
+            FittedTransform::Center(mean) => vector.iter().zip(mean).map(|(x, m)| x - m).collect(),
+            FittedTransform::Project { mean, rows } => {
+                let centered;
+                let vector = match mean {
+                    Some(mean) => {
+                        centered = vector.iter().zip(mean).map(|(x, m)| x - m).collect::<Vec<_>>();
+                        &centered[..]
+                    }
+                    None => vector,
+                };
+                rows.iter().map(|row| dot(row, vector)).collect()
+            }
+            FittedTransform::SparseProject(rows) => rows
+                .iter()
+                .map(|row| row.iter().map(|&(i, value)| vector[i as usize] * value).sum())
+                .collect(),
+        }
+    }
+}
+
+/// Applies a list of transforms to the vectors of a dataset when they are read.
+///
+/// The mean and the principal components are fitted on a sample of the vectors, and the
+/// sample and the random projections are drawn with [`RNG_SEED`], so that the same
+/// transforms always produce the same vectors.
+#[derive(Debug, Clone)]
+pub struct TransformedView {
+    dataset: Arc<dyn VectorDataset>,
+    transforms: Vec<Transform>,
+    fitted: Vec<FittedTransform>,
+    name: String,
+    dimensions: usize,
+    /// The file the transformed vectors were written into, they are read from it if present.
+    cache: Option<Arc<dyn VectorDataset>>,
+}
+
+impl TransformedView {
+    pub fn new(
+        dataset: Arc<dyn VectorDataset>,
+        transforms: &[Transform],
+    ) -> anyhow::Result<TransformedView> {
+        let mut rng = StdRng::seed_from_u64(RNG_SEED);
+        let needs_sample =
+            transforms.iter().any(|t| matches!(t, Transform::Center | Transform::Pca(_)));
+        let mut sample: Vec<Vec<f32>> = if needs_sample {
+            ensure!(
+                !dataset.is_empty(),
+                "Cannot fit the transforms on the empty dataset {}",
+                dataset.name()
+            );
+            let mut indices =
+                index::sample(&mut rng, dataset.len(), FIT_SAMPLE.min(dataset.len())).into_vec();
+            indices.sort_unstable();
+            indices.into_iter().map(|i| dataset.vector(i).unwrap().into_owned()).collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut dimensions = dataset.dimensions();
+        let mut fitted = Vec::with_capacity(transforms.len());
+        for transform in transforms {
+            let output = transform.output_dimensions(dimensions);
+            ensure!(
+                (1..=dimensions).contains(&output),
+                "Cannot apply `{transform}` to the {dimensions} dimensions vectors of {}",
+                dataset.name()
+            );
+            let transform = match transform {
+                Transform::Normalize => FittedTransform::Normalize,
+                Transform::Center => FittedTransform::Center(mean(&sample, dimensions)),
+                Transform::Pca(components) => {
+                    let mean = mean(&sample, dimensions);
+                    let rows = principal_components(&sample, &mean, *components, &mut rng);
+                    FittedTransform::Project { mean: Some(mean), rows }
+                }
+                Transform::GaussianProjection(output) => {
+                    let scale = (*output as f32).sqrt().recip();
+                    let rows = (0..*output)
+                        .map(|_| {
+                            (0..dimensions)
+                                .map(|_| scale * rng.sample::<f32, _>(StandardNormal))
+                                .collect()
+                        })
+                        .collect();
+                    FittedTransform::Project { mean: None, rows }
+                }
+                Transform::SparseProjection(output) => {
+                    // Every value is non-zero with a probability of 1/sqrt(dimensions).
+                    let density = (dimensions as f64).sqrt().recip();
+                    let value = ((dimensions as f32).sqrt() / *output as f32).sqrt();
+                    let rows = (0..*output)
+                        .map(|_| {
+                            (0..dimensions as u32)
+                                .filter_map(|i| {
+                                    let sign = if rng.gen() { value } else { -value };
+                                    rng.gen_bool(density).then_some((i, sign))
+                                })
+                                .collect()
+                        })
+                        .collect();
+                    FittedTransform::SparseProject(rows)
+                }
+            };
+            sample.par_iter_mut().for_each(|vector| *vector = transform.apply(vector));
+            fitted.push(transform);
+            dimensions = output;
+        }
+
+        let name = format!("{} ({})", dataset.name(), transforms.iter().join("+"));
+        Ok(TransformedView {
+            dataset,
+            transforms: transforms.to_vec(),
+            fitted,
+            name,
+            dimensions,
+            cache: None,
+        })
+    }
+
+    /// The dataset whose vectors are transformed.
+    pub fn inner(&self) -> &Arc<dyn VectorDataset> {
+        &self.dataset
+    }
+
+    /// The `.mat` file the transformed vectors are cached into, next to the original file and
+    /// named after the checksum of the original file, e.g. `assets/hn-posts.center-pca256.1a2b3c4d.mat`.
+    pub fn cache_path(&self) -> PathBuf {
+        let path = self.dataset.path();
+        let stem = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let transforms = self.transforms.iter().map(|t| t.to_string().replace(':', "")).join("-");
+        let checksum = self.dataset.checksum();
+        path.with_file_name(format!("{stem}.{transforms}.{checksum:08x}.mat"))
+    }
+
+    /// Reads the transformed vectors from [`Self::cache_path`], the file is generated if it doesn't exist.
+    ///
+    /// A new file is generated when the original dataset changes, the previous one is left behind.
+    pub fn cached(mut self) -> anyhow::Result<TransformedView> {
+        let path = self.cache_path();
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp = path.with_extension("mat.tmp");
+            let file =
+                File::create(&tmp).with_context(|| format!("while creating {}", tmp.display()))?;
+            self.write_into(BufWriter::new(file))
+                .with_context(|| format!("while writing {}", tmp.display()))?;
+            fs::rename(&tmp, &path)?;
+        }
+
+        let cache = open_dataset(&path, Some(&self.name), None)?;
+        ensure!(
+            cache.len() == self.len() && cache.dimensions() == self.dimensions,
+            "{} contains {} vectors of {} dimensions instead of {} vectors of {} dimensions, delete it to regenerate it",
+            path.display(),
+            cache.len(),
+            cache.dimensions(),
+            self.len(),
+            self.dimensions
+        );
+        self.cache = Some(cache);
+        Ok(self)
+    }
+
+    /// Writes the whole transformed dataset into a `.mat` file.
+    pub fn write_into(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        const CHUNK_SIZE: usize = 16 * 1024;
+
+        let header = MatHeader::new(&self.name, Dtype::F32, self.dimensions, self.len());
+        header.write_into(&mut writer)?;
+        for start in (0..self.len()).step_by(CHUNK_SIZE) {
+            let end = (start + CHUNK_SIZE).min(self.len());
+            let vectors: Vec<_> =
+                (start..end).into_par_iter().map(|i| self.vector(i).unwrap()).collect();
+            for vector in vectors {
+                writer.write_all(bytemuck::cast_slice(&vector))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl VectorDataset for TransformedView {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn path(&self) -> &Path {
+        self.dataset.path()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn file_dimensions(&self) -> usize {
+        self.dataset.file_dimensions()
+    }
+
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn file_size(&self) -> usize {
+        self.dataset.file_size()
+    }
+
+    /// The checksum of the original file.
+    fn checksum(&self) -> u32 {
+        self.dataset.checksum()
+    }
+
+    fn transforms(&self) -> &[Transform] {
+        &self.transforms
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        if let Some(cache) = &self.cache {
+            return cache.vector(index);
+        }
+        let vector = self.dataset.vector(index)?;
+        let vector = self.fitted.iter().fold(vector.into_owned(), |v, t| t.apply(&v));
+        Some(Cow::Owned(vector))
+    }
+}
+
+/// Applies the transforms to every dataset, the transformed vectors are written
+/// into a `.mat` file next to the original one if `cache` is set.
+pub fn transform_datasets(
+    datasets: Vec<Arc<dyn VectorDataset>>,
+    transforms: &[Transform],
+    cache: bool,
+) -> anyhow::Result<Vec<Arc<dyn VectorDataset>>> {
+    if transforms.is_empty() {
+        return Ok(datasets);
+    }
+    datasets
+        .into_iter()
+        .map(|dataset| {
+            let view = TransformedView::new(dataset, transforms)?;
+            let view = if cache { view.cached()? } else { view };
+            Ok(Arc::new(view) as Arc<_>)
+        })
+        .collect()
+}
+
+fn dot(left: &[f32], right: &[f32]) -> f32 {
+    left.iter().zip(right).map(|(l, r)| l * r).sum()
+}
+
+fn mean(vectors: &[Vec<f32>], dimensions: usize) -> Vec<f32> {
+    let mut sum = vec![0.0f64; dimensions];
+    for vector in vectors {
+        sum.iter_mut().zip(vector).for_each(|(s, x)| *s += *x as f64);
+    }
+    sum.iter().map(|s| (s / vectors.len() as f64) as f32).collect()
+}
+
+/// Finds the `components` principal components of the vectors, sorted by decreasing variance,
+/// with an orthogonal iteration on their covariance matrix.
+///
+/// When two variances are too close for the iteration to separate their components, the basis
+/// still spans the same subspace and the distances between the projected vectors are the same.
+fn principal_components(
+    vectors: &[Vec<f32>],
+    mean: &[f32],
+    components: usize,
+    rng: &mut StdRng,
+) -> Vec<Vec<f32>> {
+    let dimensions = mean.len();
+    // The centered vectors, stored by dimension to compute the covariances with dot products.
+    let columns: Vec<Vec<f32>> = (0..dimensions)
+        .into_par_iter()
+        .map(|j| vectors.iter().map(|v| v[j] - mean[j]).collect())
+        .collect();
+    let n = vectors.len() as f32;
+    let covariance: Vec<Vec<f32>> = (0..dimensions)
+        .into_par_iter()
+        .map(|i| (0..dimensions).map(|j| dot(&columns[i], &columns[j]) / n).collect())
+        .collect();
+
+    let mut basis: Vec<Vec<f32>> = (0..components)
+        .map(|_| (0..dimensions).map(|_| rng.sample(StandardNormal)).collect())
+        .collect();
+    orthonormalize(&mut basis);
+    for _ in 0..PCA_ITERATIONS {
+        basis =
+            basis.par_iter().map(|q| covariance.iter().map(|row| dot(row, q)).collect()).collect();
+        orthonormalize(&mut basis);
+    }
+    basis
+}
+
+/// The modified Gram-Schmidt process, a vector that becomes null is replaced by zeros.
+fn orthonormalize(vectors: &mut [Vec<f32>]) {
+    for i in 0..vectors.len() {
+        let (previous, rest) = vectors.split_at_mut(i);
+        let vector = &mut rest[0];
+        for other in previous.iter() {
+            let projection = dot(vector, other);
+            vector.iter_mut().zip(other).for_each(|(x, o)| *x -= projection * o);
+        }
+        let norm = dot(vector, vector).sqrt();
+        if norm > f32::EPSILON {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        } else {
+            vector.iter_mut().for_each(|x| *x = 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_transforms() {
+        assert_eq!("normalize".parse(), Ok(Transform::Normalize));
+        assert_eq!(" Center ".parse(), Ok(Transform::Center));
+        assert_eq!("PCA:256".parse(), Ok(Transform::Pca(256)));
+        assert_eq!("gaussian-projection:64".parse(), Ok(Transform::GaussianProjection(64)));
+        assert_eq!("sparse-projection:32".parse(), Ok(Transform::SparseProjection(32)));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for transform in [
+            Transform::Normalize,
+            Transform::Center,
+            Transform::Pca(256),
+            Transform::GaussianProjection(64),
+            Transform::SparseProjection(32),
+        ] {
+            assert_eq!(transform.to_string().parse(), Ok(transform));
+            let json = serde_json::to_string(&transform).unwrap();
+            assert_eq!(serde_json::from_str::<Transform>(&json).unwrap(), transform);
+        }
+    }
+
+    #[test]
+    fn parse_malformed_transforms() {
+        for s in ["", "rotate", "pca", "pca:", "pca:-1", "pca:0", "pca:1.5", "normalize:3"] {
+            assert!(s.parse::<Transform>().is_err(), "`{s}` should not parse");
+        }
+    }
+}
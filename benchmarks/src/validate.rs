@@ -0,0 +1,221 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use byte_unit::{Byte, UnitType};
+use ordered_float::OrderedFloat;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use crate::VectorDataset;
+
+/// A vector whose norm differs from 1 by less than this value is considered normalized.
+const NORMALIZED_TOLERANCE: f32 = 1e-3;
+
+/// What was found while scanning every vector of a dataset.
+#[derive(Debug, Clone)]
+pub struct DatasetReport {
+    pub name: String,
+    pub path: PathBuf,
+    pub rows: usize,
+    pub dimensions: usize,
+    /// The size of the file in bytes.
+    pub file_size: usize,
+    pub nan_values: usize,
+    /// The number of rows containing at least one NaN and the first of them.
+    pub nan_rows: (usize, Option<usize>),
+    pub infinite_values: usize,
+    /// The number of rows containing at least one infinite value and the first of them.
+    pub infinite_rows: (usize, Option<usize>),
+    /// The number of rows whose norm is zero, and the first of them.
+    /// The cosine distance is undefined for them.
+    pub zero_norm_rows: (usize, Option<usize>),
+    /// The number of rows whose norm is 1.
+    pub normalized_rows: usize,
+    /// The distribution of the norms of the finite and non-zero rows.
+    pub norms: Option<NormDistribution>,
+    /// The number of rows that are an exact copy of a previous row.
+    pub duplicate_rows: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NormDistribution {
+    pub min: f32,
+    pub p1: f32,
+    pub median: f32,
+    pub p99: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+struct RowStats {
+    nan: usize,
+    infinite: usize,
+    norm: f32,
+    hash: u64,
+}
+
+impl DatasetReport {
+    /// Scans the whole dataset in parallel.
+    pub fn new(dataset: &dyn VectorDataset) -> DatasetReport {
+        let rows: Vec<_> = (0..dataset.len())
+            .into_par_iter()
+            .map(|i| {
+                let vector = dataset.vector(i).unwrap();
+                let mut hasher = DefaultHasher::new();
+                let mut stats = RowStats { nan: 0, infinite: 0, norm: 0.0, hash: 0 };
+                for x in vector.iter() {
+                    stats.nan += x.is_nan() as usize;
+                    stats.infinite += x.is_infinite() as usize;
+                    stats.norm += x * x;
+                    x.to_bits().hash(&mut hasher);
+                }
+                stats.norm = stats.norm.sqrt();
+                stats.hash = hasher.finish();
+                stats
+            })
+            .collect();
+
+        let count_rows = |predicate: &dyn Fn(&RowStats) -> bool| {
+            let count = rows.iter().filter(|row| predicate(row)).count();
+            (count, rows.iter().position(predicate))
+        };
+        let mut norms: Vec<_> = rows
+            .iter()
+            .map(|row| row.norm)
+            .filter(|norm| norm.is_finite() && *norm != 0.0)
+            .collect();
+        norms.par_sort_unstable_by_key(|&norm| OrderedFloat(norm));
+
+        DatasetReport {
+            name: dataset.name().to_string(),
+            path: dataset.path().to_path_buf(),
+            rows: dataset.len(),
+            dimensions: dataset.dimensions(),
+            file_size: dataset.file_size(),
+            nan_values: rows.iter().map(|row| row.nan).sum(),
+            nan_rows: count_rows(&|row| row.nan != 0),
+            infinite_values: rows.iter().map(|row| row.infinite).sum(),
+            infinite_rows: count_rows(&|row| row.infinite != 0),
+            zero_norm_rows: count_rows(&|row| row.norm == 0.0),
+            normalized_rows: rows
+                .iter()
+                .filter(|row| (row.norm - 1.0).abs() <= NORMALIZED_TOLERANCE)
+                .count(),
+            norms: NormDistribution::new(&norms),
+            duplicate_rows: count_duplicates(dataset, &rows),
+        }
+    }
+
+    /// Whether the dataset contains values that make the benchmarks meaningless:
+    /// NaNs, infinite values or vectors without direction.
+    pub fn has_errors(&self) -> bool {
+        self.rows == 0
+            || self.nan_rows.0 != 0
+            || self.infinite_rows.0 != 0
+            || self.zero_norm_rows.0 != 0
+    }
+}
+
+impl NormDistribution {
+    /// Computes the distribution of the sorted `norms`.
+    fn new(norms: &[f32]) -> Option<NormDistribution> {
+        let percentile = |p: f32| norms[((norms.len() - 1) as f32 * p).round() as usize];
+        let mean = norms.iter().map(|&n| n as f64).sum::<f64>() / norms.len() as f64;
+        (!norms.is_empty()).then(|| NormDistribution {
+            min: norms[0],
+            p1: percentile(0.01),
+            median: percentile(0.5),
+            p99: percentile(0.99),
+            max: norms[norms.len() - 1],
+            mean: mean as f32,
+        })
+    }
+}
+
+/// Counts the rows equal to a previous row, the rows with the same hash are compared entirely.
+fn count_duplicates(dataset: &dyn VectorDataset, rows: &[RowStats]) -> usize {
+    let mut hashes: Vec<_> = rows.iter().enumerate().map(|(i, row)| (row.hash, i)).collect();
+    hashes.par_sort_unstable();
+
+    let bits =
+        |i: usize| dataset.vector(i).unwrap().iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+    let mut duplicates = 0;
+    for group in hashes.chunk_by(|(a, _), (b, _)| a == b).filter(|group| group.len() > 1) {
+        let mut distinct: Vec<Vec<u32>> = Vec::new();
+        for &(_, i) in group {
+            let vector = bits(i);
+            if distinct.contains(&vector) {
+                duplicates += 1;
+            } else {
+                distinct.push(vector);
+            }
+        }
+    }
+    duplicates
+}
+
+impl fmt::Display for DatasetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const ERROR: &str = "\x1b[1;31merror\x1b[0m";
+        const WARNING: &str = "\x1b[1;33mwarning\x1b[0m";
+
+        writeln!(f, "{} ({})", self.name, self.path.display())?;
+        writeln!(
+            f,
+            "  {} vectors of \x1b[1m{}\x1b[0m dimensions, {:.2}",
+            self.rows,
+            self.dimensions,
+            Byte::from_u64(self.file_size as u64).get_appropriate_unit(UnitType::Binary)
+        )?;
+        if self.rows == 0 {
+            writeln!(f, "  {ERROR}: the dataset is empty")?;
+        }
+
+        let rows = [
+            ("contain NaNs", self.nan_rows, Some(self.nan_values)),
+            ("contain infinite values", self.infinite_rows, Some(self.infinite_values)),
+            ("have a norm of zero, their cosine is undefined", self.zero_norm_rows, None),
+        ];
+        for (problem, (count, first), values) in rows {
+            if count == 0 {
+                continue;
+            }
+            write!(f, "  {ERROR}: {count} vectors {problem}")?;
+            if let Some(values) = values {
+                write!(f, " ({values} values)")?;
+            }
+            writeln!(f, ", the first one is the vector {}", first.unwrap())?;
+        }
+
+        if let Some(NormDistribution { min, p1, median, p99, max, mean }) = self.norms {
+            writeln!(
+                f,
+                "  norms: min {min:.4}, p1 {p1:.4}, median {median:.4}, p99 {p99:.4}, max {max:.4}, mean {mean:.4}"
+            )?;
+        }
+        if self.rows != 0 && self.normalized_rows == self.rows {
+            writeln!(f, "  all the vectors are normalized")?;
+        } else if self.rows != 0 {
+            writeln!(
+                f,
+                "  {WARNING}: {} vectors out of {} are not normalized",
+                self.rows - self.normalized_rows,
+                self.rows
+            )?;
+        }
+
+        if self.duplicate_rows != 0 {
+            writeln!(
+                f,
+                "  {WARNING}: {} vectors are exact duplicates of another vector",
+                self.duplicate_rows
+            )?;
+        } else {
+            writeln!(f, "  no duplicate vectors")?;
+        }
+
+        Ok(())
+    }
+}
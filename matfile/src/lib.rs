@@ -0,0 +1,308 @@
+//! The `.mat` format shared by `parquet2mat` and the benchmarks.
+//!
+//! A `.mat` file is a flat little-endian matrix, one vector after the other,
+//! prefixed by a header describing it:
+//!
+//! | bytes | content                                          |
+//! |-------|--------------------------------------------------|
+//! | 8     | the magic bytes `\x93MATLE\0\0`                  |
+//! | 2     | the version of the header, `u16`                 |
+//! | 2     | the type of the values, `u16`, see [`Dtype`]     |
+//! | 4     | the number of dimensions, `u32`                  |
+//! | 8     | the number of rows, `u64`                        |
+//! | 4     | the length of the header with its padding, `u32` |
+//! | 4     | the length of the name, `u32`                    |
+//! | n     | the name of the dataset in UTF-8                 |
+//! | 4     | the CRC32 of the vectors, `u32`, since version 2 |
+//!
+//! The checksum is optional, the headers without it are written in version 1 so that
+//! the older tools can still read them. The header is padded with zeros up to a multiple of [`HEADER_ALIGNMENT`] bytes
+//! so that the vectors stay aligned once the file is memory mapped.
+//!
+//! The files generated before the header existed are still readable, but
+//! their number of dimensions must be known beforehand.
+//!
+//! The header of the NumPy `.npy` files is also handled here, see [`NpyHeader`],
+//! and so are the manifests of the matrices split into several files, see [`ShardManifest`].
+
+mod npy;
+mod shards;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+pub use npy::*;
+pub use shards::*;
+
+/// The bytes every `.mat` file with a header starts with.
+pub const MAGIC: &[u8; 8] = b"\x93MATLE\0\0";
+/// The latest version of the header, the one written with a checksum.
+pub const VERSION: u16 = 2;
+/// The version of the headers written without checksum.
+const VERSION_WITHOUT_CHECKSUM: u16 = 1;
+/// The vectors start at an offset that is a multiple of this value.
+pub const HEADER_ALIGNMENT: usize = 64;
+
+const FIXED_HEADER_LEN: usize = 32;
+
+/// The type of the values stored in a `.mat` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtype {
+    F32,
+    /// IEEE 754 half precision, halves the size of the files.
+    F16,
+    /// The 16 upper bits of an `f32`, as stored by most of the ML frameworks.
+    BF16,
+}
+
+impl Dtype {
+    /// The size of one value in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F16 | Dtype::BF16 => 2,
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            Dtype::F32 => 0,
+            Dtype::F16 => 1,
+            Dtype::BF16 => 2,
+        }
+    }
+
+    fn from_u16(dtype: u16) -> Option<Dtype> {
+        match dtype {
+            0 => Some(Dtype::F32),
+            1 => Some(Dtype::F16),
+            2 => Some(Dtype::BF16),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Dtype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dtype::F32 => f.write_str("f32"),
+            Dtype::F16 => f.write_str("f16"),
+            Dtype::BF16 => f.write_str("bf16"),
+        }
+    }
+}
+
+impl FromStr for Dtype {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Dtype::F32),
+            "f16" => Ok(Dtype::F16),
+            "bf16" => Ok(Dtype::BF16),
+            s => Err(format!("unknown dtype `{s}`, expected `f32`, `f16` or `bf16`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatHeader {
+    pub version: u16,
+    pub dtype: Dtype,
+    pub dimensions: usize,
+    pub rows: usize,
+    pub name: String,
+    /// The CRC32 of the vectors following the header, if known.
+    pub checksum: Option<u32>,
+}
+
+impl MatHeader {
+    pub fn new(name: impl Into<String>, dtype: Dtype, dimensions: usize, rows: usize) -> Self {
+        MatHeader {
+            version: VERSION_WITHOUT_CHECKSUM,
+            dtype,
+            dimensions,
+            rows,
+            name: name.into(),
+            checksum: None,
+        }
+    }
+
+    /// Records the CRC32 of the vectors, the readers refuse the vectors that don't match it.
+    pub fn with_checksum(mut self, checksum: u32) -> Self {
+        self.version = VERSION;
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// The number of bytes taken by the header, padding included.
+    /// The first vector starts right after.
+    pub fn size(&self) -> usize {
+        let checksum_len = if self.checksum.is_some() { 4 } else { 0 };
+        (FIXED_HEADER_LEN + self.name.len() + checksum_len).next_multiple_of(HEADER_ALIGNMENT)
+    }
+
+    /// The size in bytes of the vectors following the header.
+    pub fn data_len(&self) -> usize {
+        self.rows * self.dimensions * self.dtype.size()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.dtype.to_u16().to_le_bytes());
+        bytes.extend_from_slice(&(self.dimensions as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.rows as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.size() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.name.as_bytes());
+        if let Some(checksum) = self.checksum {
+            bytes.extend_from_slice(&checksum.to_le_bytes());
+        }
+        bytes.resize(self.size(), 0);
+        bytes
+    }
+
+    pub fn write_into(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&self.encode())
+    }
+
+    /// Reads the header at the start of a reader, see [`MatHeader::decode`].
+    pub fn read_from(mut reader: impl Read) -> anyhow::Result<Option<MatHeader>> {
+        let mut bytes = Vec::with_capacity(HEADER_ALIGNMENT);
+        (&mut reader).take(FIXED_HEADER_LEN as u64).read_to_end(&mut bytes)?;
+        if bytes.len() == FIXED_HEADER_LEN && bytes.starts_with(MAGIC) {
+            let version = u16::from_le_bytes(bytes[8..10].try_into().unwrap());
+            let name_len = u32::from_le_bytes(bytes[28..32].try_into().unwrap());
+            let checksum_len = if version >= 2 { 4 } else { 0 };
+            reader.take(name_len as u64 + checksum_len).read_to_end(&mut bytes)?;
+        }
+        MatHeader::decode(&bytes)
+    }
+
+    /// Decodes the header at the start of a `.mat` file.
+    ///
+    /// Returns `None` if the file doesn't start with the [`MAGIC`] bytes,
+    /// which means it was generated before the header existed.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Option<MatHeader>> {
+        if !bytes.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        ensure!(bytes.len() >= FIXED_HEADER_LEN, "The header is truncated");
+
+        let u16_at =
+            |offset: usize| u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap());
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        let version = u16_at(8);
+        if version > VERSION {
+            bail!(
+                "Unsupported header version {version}, the latest supported version is {VERSION}"
+            );
+        }
+        let dtype = match Dtype::from_u16(u16_at(10)) {
+            Some(dtype) => dtype,
+            None => bail!("Unknown dtype {}", u16_at(10)),
+        };
+        let dimensions = u32_at(12) as usize;
+        let rows = u64_at(16) as usize;
+        let header_len = u32_at(24) as usize;
+        let name_len = u32_at(28) as usize;
+
+        ensure!(dimensions != 0, "The header declares zero dimensions");
+        let name = bytes
+            .get(FIXED_HEADER_LEN..FIXED_HEADER_LEN + name_len)
+            .context("The name of the dataset is truncated")?;
+        let name =
+            String::from_utf8(name.to_vec()).context("The name of the dataset is not UTF-8")?;
+
+        let checksum = match version {
+            0 | 1 => None,
+            _ => {
+                let offset = FIXED_HEADER_LEN + name_len;
+                let checksum =
+                    bytes.get(offset..offset + 4).context("The checksum is truncated")?;
+                Some(u32::from_le_bytes(checksum.try_into().unwrap()))
+            }
+        };
+
+        let header = MatHeader { version, dtype, dimensions, rows, name, checksum };
+        ensure!(
+            header.size() == header_len,
+            "The header declares a length of {header_len} bytes but should be {} bytes long",
+            header.size()
+        );
+        Ok(Some(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trips() {
+        let headers = [
+            MatHeader::new("hn-posts", Dtype::F32, 768, 1_000_000),
+            MatHeader::new("", Dtype::F16, 3, 0),
+            MatHeader::new("datacomp", Dtype::BF16, 512, 42).with_checksum(0xdeadbeef),
+        ];
+        for header in headers {
+            let bytes = header.encode();
+            assert_eq!(bytes.len(), header.size());
+            assert_eq!(bytes.len() % HEADER_ALIGNMENT, 0);
+            assert_eq!(MatHeader::decode(&bytes).unwrap(), Some(header.clone()));
+            assert_eq!(MatHeader::read_from(&bytes[..]).unwrap(), Some(header));
+        }
+    }
+
+    #[test]
+    fn header_version_depends_on_the_checksum() {
+        let header = MatHeader::new("a", Dtype::F32, 2, 3);
+        assert_eq!(header.version, 1);
+        let header = header.with_checksum(7);
+        assert_eq!(header.version, VERSION);
+        // The checksum follows the name.
+        let bytes = header.encode();
+        assert_eq!(bytes[FIXED_HEADER_LEN + 1..FIXED_HEADER_LEN + 5], 7u32.to_le_bytes());
+    }
+
+    #[test]
+    fn decode_files_without_header() {
+        assert_eq!(MatHeader::decode(&[0; 64]).unwrap(), None);
+        assert_eq!(MatHeader::decode(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn decode_malformed_headers() {
+        let valid = MatHeader::new("name", Dtype::F32, 4, 2).with_checksum(1).encode();
+        let with = |offset: usize, value: &[u8]| {
+            let mut bytes = valid.clone();
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+            bytes
+        };
+
+        // truncated
+        assert!(MatHeader::decode(&valid[..FIXED_HEADER_LEN - 1]).is_err());
+        assert!(MatHeader::decode(&valid[..FIXED_HEADER_LEN + 2]).is_err());
+        assert!(MatHeader::decode(&valid[..FIXED_HEADER_LEN + 6]).is_err());
+        // unsupported version
+        assert!(MatHeader::decode(&with(8, &3u16.to_le_bytes())).is_err());
+        // unknown dtype
+        assert!(MatHeader::decode(&with(10, &9u16.to_le_bytes())).is_err());
+        // zero dimensions
+        assert!(MatHeader::decode(&with(12, &0u32.to_le_bytes())).is_err());
+        // wrong header length
+        assert!(MatHeader::decode(&with(24, &128u32.to_le_bytes())).is_err());
+        // name longer than the header
+        assert!(MatHeader::decode(&with(28, &60u32.to_le_bytes())).is_err());
+        // name not UTF-8
+        assert!(MatHeader::decode(&with(FIXED_HEADER_LEN, &[0xff])).is_err());
+    }
+}
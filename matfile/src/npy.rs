@@ -0,0 +1,242 @@
+//! The header of the NumPy `.npy` files, as described in
+//! <https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html>.
+//!
+//! Only the two-dimensional, C-ordered and little-endian arrays of floats are supported,
+//! i.e. the matrices exported with `np.save("embeddings.npy", embeddings)`, and the arrays
+//! of `i32` storing the ids of the nearest neighbours of a set of queries.
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Context};
+
+/// The bytes every `.npy` file starts with.
+pub const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// The length of the headers we write, always enough to store the largest shape.
+/// It is a multiple of 64 so that the array stays aligned once the file is memory mapped.
+const NPY_WRITTEN_HEADER_LEN: usize = 128;
+
+/// The type of the values stored in a `.npy` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NpyDtype {
+    F16,
+    F32,
+    F64,
+    /// The ids of the nearest neighbours, never vectors.
+    I32,
+}
+
+impl NpyDtype {
+    /// The size of one value in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            NpyDtype::F16 => 2,
+            NpyDtype::F32 | NpyDtype::I32 => 4,
+            NpyDtype::F64 => 8,
+        }
+    }
+
+    fn descr(self) -> &'static str {
+        match self {
+            NpyDtype::F16 => "<f2",
+            NpyDtype::F32 => "<f4",
+            NpyDtype::F64 => "<f8",
+            NpyDtype::I32 => "<i4",
+        }
+    }
+
+    fn from_descr(descr: &str) -> anyhow::Result<NpyDtype> {
+        match descr {
+            "<f2" => Ok(NpyDtype::F16),
+            "<f4" => Ok(NpyDtype::F32),
+            "<f8" => Ok(NpyDtype::F64),
+            "<i4" => Ok(NpyDtype::I32),
+            descr if descr.starts_with('>') => {
+                bail!("Big-endian arrays (`{descr}`) are not supported, convert them with `astype`")
+            }
+            descr => bail!("Unsupported dtype `{descr}`, only f16, f32, f64 and i32 are supported"),
+        }
+    }
+}
+
+impl fmt::Display for NpyDtype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NpyDtype::F16 => f.write_str("f16"),
+            NpyDtype::F32 => f.write_str("f32"),
+            NpyDtype::F64 => f.write_str("f64"),
+            NpyDtype::I32 => f.write_str("i32"),
+        }
+    }
+}
+
+/// The header of a `.npy` file containing a matrix of `rows` vectors of `dimensions` values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpyHeader {
+    pub dtype: NpyDtype,
+    pub rows: usize,
+    pub dimensions: usize,
+    /// The offset of the array in the file, right after the header.
+    pub data_offset: usize,
+}
+
+impl NpyHeader {
+    pub fn new(dtype: NpyDtype, rows: usize, dimensions: usize) -> NpyHeader {
+        NpyHeader { dtype, rows, dimensions, data_offset: NPY_WRITTEN_HEADER_LEN }
+    }
+
+    /// The size in bytes of the array following the header.
+    pub fn data_len(&self) -> usize {
+        self.rows * self.dimensions * self.dtype.size()
+    }
+
+    /// Encodes a version 1.0 header, always 128 bytes long so that
+    /// it can be rewritten once the shape is known.
+    pub fn encode(&self) -> Vec<u8> {
+        let dict = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.dtype.descr(),
+            self.rows,
+            self.dimensions
+        );
+        let mut bytes = Vec::with_capacity(NPY_WRITTEN_HEADER_LEN);
+        bytes.extend_from_slice(NPY_MAGIC);
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&((NPY_WRITTEN_HEADER_LEN - 10) as u16).to_le_bytes());
+        bytes.extend_from_slice(dict.as_bytes());
+        bytes.resize(NPY_WRITTEN_HEADER_LEN - 1, b' ');
+        bytes.push(b'\n');
+        bytes
+    }
+
+    /// Decodes the header at the start of a `.npy` file.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<NpyHeader> {
+        ensure!(bytes.starts_with(NPY_MAGIC), "This is not a `.npy` file");
+        ensure!(bytes.len() >= 10, "The header is truncated");
+
+        let (dict_len, dict_start) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 => {
+                ensure!(bytes.len() >= 12, "The header is truncated");
+                (u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize, 12)
+            }
+            version => bail!("Unsupported `.npy` version {version}"),
+        };
+        let dict =
+            bytes.get(dict_start..dict_start + dict_len).context("The header is truncated")?;
+        let dict = std::str::from_utf8(dict).context("The header is not UTF-8")?;
+
+        let descr = dict_value(dict, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+        let dtype = NpyDtype::from_descr(descr)?;
+        match dict_value(dict, "fortran_order")? {
+            "False" => (),
+            "True" => bail!("Fortran-ordered arrays are not supported, use `np.ascontiguousarray`"),
+            value => bail!("Invalid `fortran_order` value `{value}`"),
+        }
+        let shape = dict_value(dict, "shape")?;
+        let shape = shape
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse().with_context(|| format!("Invalid shape `{shape}`")))
+            .collect::<anyhow::Result<Vec<usize>>>()?;
+        let [rows, dimensions] = shape[..] else {
+            bail!("Only two-dimensional arrays are supported but the shape is {shape:?}");
+        };
+        ensure!(dimensions != 0, "The array contains vectors of zero dimensions");
+
+        Ok(NpyHeader { dtype, rows, dimensions, data_offset: dict_start + dict_len })
+    }
+}
+
+/// Returns the raw value associated with `key` in the Python dictionary of the header.
+fn dict_value<'a>(dict: &'a str, key: &str) -> anyhow::Result<&'a str> {
+    let start = [format!("'{key}':"), format!("\"{key}\":")]
+        .iter()
+        .find_map(|pattern| dict.find(pattern.as_str()).map(|i| i + pattern.len()))
+        .with_context(|| format!("The header doesn't contain the `{key}` key"))?;
+    let value = dict[start..].trim_start();
+    let end = if value.starts_with('(') {
+        value.find(')').map(|i| i + 1)
+    } else {
+        value.find([',', '}'])
+    };
+    Ok(value[..end.unwrap_or(value.len())].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a header the way NumPy writes it, padded to a multiple of 64 bytes.
+    fn numpy_header(major: u8, dict: &str) -> Vec<u8> {
+        let prefix_len = if major == 1 { 10 } else { 12 };
+        let len = (prefix_len + dict.len() + 1).next_multiple_of(64);
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[major, 0]);
+        match major {
+            1 => bytes.extend_from_slice(&((len - prefix_len) as u16).to_le_bytes()),
+            _ => bytes.extend_from_slice(&((len - prefix_len) as u32).to_le_bytes()),
+        }
+        bytes.extend_from_slice(dict.as_bytes());
+        bytes.resize(len - 1, b' ');
+        bytes.push(b'\n');
+        bytes
+    }
+
+    #[test]
+    fn header_round_trips() {
+        for dtype in [NpyDtype::F16, NpyDtype::F32, NpyDtype::F64, NpyDtype::I32] {
+            let header = NpyHeader::new(dtype, 10_000_000_000, 4096);
+            let bytes = header.encode();
+            assert_eq!(bytes.len(), NPY_WRITTEN_HEADER_LEN);
+            assert_eq!(NpyHeader::decode(&bytes).unwrap(), header);
+        }
+    }
+
+    #[test]
+    fn decode_numpy_headers() {
+        let bytes = numpy_header(1, "{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }");
+        let expected =
+            NpyHeader { dtype: NpyDtype::F32, rows: 3, dimensions: 2, data_offset: bytes.len() };
+        assert_eq!(NpyHeader::decode(&bytes).unwrap(), expected);
+
+        let bytes = numpy_header(2, r#"{"descr": "<f2", "fortran_order": False, "shape": (5, 7)}"#);
+        let expected =
+            NpyHeader { dtype: NpyDtype::F16, rows: 5, dimensions: 7, data_offset: bytes.len() };
+        assert_eq!(NpyHeader::decode(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn decode_malformed_headers() {
+        let dict = |descr: &str, fortran_order: &str, shape: &str| {
+            numpy_header(
+                1,
+                &format!(
+                    "{{'descr': '{descr}', 'fortran_order': {fortran_order}, 'shape': {shape}, }}"
+                ),
+            )
+        };
+        let valid = dict("<f4", "False", "(3, 2)");
+        assert!(NpyHeader::decode(&valid).is_ok());
+
+        assert!(NpyHeader::decode(b"\x93MATLE\0\0").is_err());
+        assert!(NpyHeader::decode(&valid[..9]).is_err());
+        assert!(NpyHeader::decode(&valid[..40]).is_err());
+        let mut version = valid.clone();
+        version[6] = 4;
+        assert!(NpyHeader::decode(&version).is_err());
+
+        assert!(NpyHeader::decode(&dict(">f4", "False", "(3, 2)")).is_err());
+        assert!(NpyHeader::decode(&dict("<u1", "False", "(3, 2)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "True", "(3, 2)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "maybe", "(3, 2)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "False", "(3,)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "False", "(3, 2, 1)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "False", "(3, 0)")).is_err());
+        assert!(NpyHeader::decode(&dict("<f4", "False", "(3, -2)")).is_err());
+        assert!(NpyHeader::decode(&numpy_header(1, "{'descr': '<f4', 'shape': (3, 2), }")).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+
+/// Describes a matrix split into several files of consecutive rows, the shards.
+///
+/// It is written next to the shards by `parquet2mat --shard-size` and the benchmarks
+/// read the shards as a single matrix, in the order of the manifest.
+///
+/// ```toml
+/// name = "datacomp-small"
+/// dimensions = 768
+/// rows = 2500000
+///
+/// [[shards]]
+/// path = "datacomp-small-00000.mat"
+/// rows = 1000000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ShardManifest {
+    pub name: String,
+    pub dimensions: usize,
+    /// The total number of rows of the shards.
+    pub rows: usize,
+    pub shards: Vec<Shard>,
+}
+
+/// A file storing some consecutive rows of a sharded matrix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Shard {
+    /// The path of the shard, relative to the manifest.
+    pub path: PathBuf,
+    pub rows: usize,
+}
+
+impl ShardManifest {
+    /// The extension of the manifest files, e.g. `datacomp-small.shards.toml`.
+    pub const EXTENSION: &'static str = "shards.toml";
+
+    pub fn read_from_path(path: impl AsRef<Path>) -> anyhow::Result<ShardManifest> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("while reading {}", path.display()))?;
+        let manifest: ShardManifest = toml::from_str(&content)
+            .with_context(|| format!("while parsing {}", path.display()))?;
+        let rows: usize = manifest.shards.iter().map(|shard| shard.rows).sum();
+        ensure!(
+            rows == manifest.rows,
+            "The shards of {} have {rows} rows in total but the manifest declares {} rows",
+            path.display(),
+            manifest.rows
+        );
+        Ok(manifest)
+    }
+
+    /// The path of every shard, resolved from the directory of the manifest at `path`.
+    pub fn shard_paths<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = PathBuf> + 'a {
+        let directory = path.parent().unwrap_or(Path::new(""));
+        self.shards.iter().map(move |shard| directory.join(&shard.path))
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use benchmarks::{open_dataset, DatasetReport, VectorDataset};
+use clap::{Args, Parser, Subcommand};
+use matfile::{Dtype, MatHeader, NpyHeader, ShardManifest};
+use parquet::ParquetMatrix;
+use write::Output;
+
+mod parquet;
+mod write;
+
+/// Inspects, slices, concatenates and converts the matrices of vectors read by the benchmarks.
+///
+/// It reads the `.mat`, `.npy`, `.npz`, `.fvecs`, `.bvecs`, `.shards.toml` and `.parquet`
+/// files and writes the `.mat`, `.npy`, `.fvecs` and `.parquet` ones.
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// The number of dimensions of the `.mat` files without header.
+    #[arg(long, global = true)]
+    dimensions: Option<usize>,
+
+    /// The column of the `.parquet` files storing the vectors, as lists of floats.
+    #[arg(long, global = true, default_value = "embedding")]
+    column: String,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the format, the shape and the statistics of the norms of every file.
+    Info { paths: Vec<PathBuf> },
+    /// Prints some vectors as JSON, one `{"row": 12, "vector": [...]}` object per line.
+    Rows {
+        path: PathBuf,
+        /// The rows to print, e.g. `0,10..20,1000..`.
+        #[arg(value_delimiter = ',', default_value = "0")]
+        rows: Vec<RowRange>,
+    },
+    /// Copies a range of rows into a new file.
+    Slice {
+        path: PathBuf,
+        /// The rows to copy, e.g. `1000..2000`, `..1000` or `1000..`.
+        rows: RowRange,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Writes the rows of every file, in order, into a single file.
+    Concat {
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Converts a file to the format given by the extension of the output.
+    Convert {
+        path: PathBuf,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// The file to write, a `.mat`, `.npy`, `.fvecs` or `.parquet` file.
+    #[arg(long)]
+    output: PathBuf,
+
+    /// The type of the values of the `.mat` and `.npy` files: `f32`, `f16` or `bf16`.
+    #[arg(long, default_value = "f32")]
+    dtype: Dtype,
+
+    /// The name written in the header of the `.mat` file, the output file name by default.
+    #[arg(long)]
+    name: Option<String>,
+}
+
+/// A range of rows, whose end defaults to the end of the file.
+#[derive(Debug, Clone, Copy)]
+struct RowRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl RowRange {
+    fn resolve(self, len: usize) -> anyhow::Result<Range<usize>> {
+        let end = self.end.unwrap_or(len);
+        ensure!(
+            self.start <= end && end <= len,
+            "the rows {}..{end} are out of the {len} rows of the file",
+            self.start
+        );
+        Ok(self.start..end)
+    }
+}
+
+impl FromStr for RowRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse =
+            |n: &str| n.trim().parse::<usize>().map_err(|e| format!("invalid row `{n}`: {e}"));
+        match s.split_once("..") {
+            Some((start, end)) => Ok(RowRange {
+                start: if start.trim().is_empty() { 0 } else { parse(start)? },
+                end: if end.trim().is_empty() { None } else { Some(parse(end)?) },
+            }),
+            None => {
+                let row = parse(s)?;
+                Ok(RowRange { start: row, end: Some(row + 1) })
+            }
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let Cli { command, dimensions, column } = Cli::parse();
+    let open = |path: &Path| -> anyhow::Result<Arc<dyn VectorDataset>> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("parquet") => Ok(Arc::new(ParquetMatrix::open(path, &column)?)),
+            _ => open_dataset(path, None, dimensions),
+        }
+    };
+
+    match command {
+        Command::Info { paths } => {
+            for path in paths {
+                let format = describe_format(&path)?;
+                let dataset = open(&path)?;
+                print!("{}", DatasetReport::new(dataset.as_ref()));
+                println!("  format: {format}\n");
+            }
+        }
+        Command::Rows { path, rows } => {
+            let dataset = open(&path)?;
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            for rows in rows {
+                for row in rows.resolve(dataset.len())? {
+                    let vector = dataset.vector(row).unwrap();
+                    let object = serde_json::json!({ "row": row, "vector": vector });
+                    writeln!(stdout, "{object}")?;
+                }
+            }
+            stdout.flush()?;
+        }
+        Command::Slice { path, rows, output } => {
+            let dataset = open(&path)?;
+            let rows = rows.resolve(dataset.len())?;
+            write(&output, &column, &[(dataset, rows)])?;
+        }
+        Command::Concat { paths, output } => {
+            let datasets = paths
+                .iter()
+                .map(|path| {
+                    let dataset = open(path)?;
+                    let rows = 0..dataset.len();
+                    Ok((dataset, rows))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            write(&output, &column, &datasets)?;
+        }
+        Command::Convert { path, output } => {
+            let dataset = open(&path)?;
+            let rows = 0..dataset.len();
+            write(&output, &column, &[(dataset, rows)])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the `rows` of every dataset, in order, into the output file,
+/// in the `column` of the `.parquet` files.
+fn write(
+    output: &OutputArgs,
+    column: &str,
+    parts: &[(Arc<dyn VectorDataset>, Range<usize>)],
+) -> anyhow::Result<()> {
+    let OutputArgs { output: path, dtype, name } = output;
+    let Some((first, _)) = parts.first() else { return Ok(()) };
+    let dimensions = first.dimensions();
+    for (dataset, _) in parts {
+        ensure!(
+            dataset.dimensions() == dimensions,
+            "{} has {} dimensions but {} has {dimensions}",
+            dataset.path().display(),
+            dataset.dimensions(),
+            first.path().display()
+        );
+    }
+
+    let rows = parts.iter().map(|(_, rows)| rows.len()).sum();
+    let vectors = parts.iter().flat_map(|(dataset, rows)| {
+        rows.clone().map(move |row| {
+            dataset.vector(row).map(|vector| vector.into_owned()).with_context(|| {
+                format!("the row {row} of {} is missing", dataset.path().display())
+            })
+        })
+    });
+    let output = Output {
+        path: path.clone(),
+        name: match name {
+            Some(name) => name.clone(),
+            None => path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into()),
+        },
+        dtype: *dtype,
+        column: column.to_string(),
+    };
+    output.write(dimensions, rows, vectors)?;
+    println!("{rows} vectors of {dimensions} dimensions written into {}.", path.display());
+    Ok(())
+}
+
+/// Describes how the vectors are stored in the file, from its header.
+fn describe_format(path: &Path) -> anyhow::Result<String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+    Ok(match extension {
+        "npy" => {
+            let mut bytes = Vec::new();
+            file.take(64 * 1024).read_to_end(&mut bytes)?;
+            let header = NpyHeader::decode(&bytes)?;
+            format!("NumPy array of {} values", header.dtype)
+        }
+        "npz" => "NumPy archive".to_string(),
+        "fvecs" => "TEXMEX fvecs, f32 values".to_string(),
+        "bvecs" => "TEXMEX bvecs, u8 values".to_string(),
+        "parquet" => "parquet".to_string(),
+        "toml" => {
+            let manifest = ShardManifest::read_from_path(path)?;
+            format!("{} shards", manifest.shards.len())
+        }
+        _ => match MatHeader::read_from(file)? {
+            Some(header) => match header.checksum {
+                Some(checksum) => format!(
+                    ".mat version {} of {} values, the CRC32 of the vectors is {checksum:08x}",
+                    header.version, header.dtype
+                ),
+                None => format!(".mat version {} of {} values", header.version, header.dtype),
+            },
+            None => ".mat without header, f32 values".to_string(),
+        },
+    })
+}
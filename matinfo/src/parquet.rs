@@ -0,0 +1,163 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float16Type, Float32Type, Float64Type};
+use arrow_array::{Array, ArrayRef, FixedSizeListArray, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use benchmarks::VectorDataset;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+
+/// The number of vectors written in every row group.
+const ROW_GROUP_SIZE: usize = 8192;
+
+/// The embeddings of a column of a parquet file, entirely loaded in memory.
+///
+/// The column is a list or a fixed-size list of `f16`, `f32` or `f64`, every
+/// embedding must be non-null and have the same number of dimensions.
+#[derive(Debug, Clone)]
+pub struct ParquetMatrix {
+    name: String,
+    path: PathBuf,
+    dimensions: usize,
+    values: Vec<f32>,
+    file_size: usize,
+    checksum: u32,
+}
+
+impl ParquetMatrix {
+    pub fn open(path: impl AsRef<Path>, column: &str) -> anyhow::Result<ParquetMatrix> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("while reading {}", path.display()))?;
+        let file = File::open(path).with_context(|| format!("while opening {}", path.display()))?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+            .with_context(|| format!("while opening {}", path.display()))?;
+        let Ok(index) = builder.schema().index_of(column) else {
+            bail!("there is no `{column}` column in {}", path.display());
+        };
+        let mask = ProjectionMask::roots(builder.parquet_schema(), [index]);
+
+        let mut dimensions = None;
+        let mut values = Vec::new();
+        for batch in builder.with_projection(mask).build()? {
+            let batch = batch?;
+            let embeddings = batch.column(0);
+            ensure!(embeddings.null_count() == 0, "the `{column}` column contains nulls");
+            for row in 0..embeddings.len() {
+                let vector = match embeddings.data_type() {
+                    DataType::List(_) => embeddings.as_list::<i32>().value(row),
+                    DataType::LargeList(_) => embeddings.as_list::<i64>().value(row),
+                    DataType::FixedSizeList(_, _) => embeddings.as_fixed_size_list().value(row),
+                    data_type => bail!("the embeddings are not lists but {data_type}"),
+                };
+                let expected = *dimensions.get_or_insert(vector.len());
+                ensure!(
+                    vector.len() == expected,
+                    "the embedding of the row {} has {} dimensions instead of {expected}",
+                    values.len() / expected,
+                    vector.len()
+                );
+                extend_with_floats(&mut values, &vector)?;
+            }
+        }
+
+        let Some(dimensions) = dimensions else {
+            bail!("there are no embeddings in {}", path.display());
+        };
+        ensure!(dimensions != 0, "the embeddings of {} are empty", path.display());
+        Ok(ParquetMatrix {
+            name: path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into()),
+            path: path.to_path_buf(),
+            dimensions,
+            values,
+            file_size: bytes.len(),
+            checksum: crc32fast::hash(&bytes),
+        })
+    }
+}
+
+fn extend_with_floats(values: &mut Vec<f32>, vector: &ArrayRef) -> anyhow::Result<()> {
+    ensure!(vector.null_count() == 0, "an embedding contains null values");
+    match vector.data_type() {
+        DataType::Float16 => {
+            values.extend(vector.as_primitive::<Float16Type>().values().iter().map(|x| x.to_f32()))
+        }
+        DataType::Float32 => values.extend(vector.as_primitive::<Float32Type>().values().iter()),
+        DataType::Float64 => {
+            values.extend(vector.as_primitive::<Float64Type>().values().iter().map(|&x| x as f32))
+        }
+        data_type => bail!("the embeddings are lists of {data_type} instead of floats"),
+    }
+    Ok(())
+}
+
+impl VectorDataset for ParquetMatrix {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn len(&self) -> usize {
+        self.values.len() / self.dimensions
+    }
+
+    fn file_size(&self) -> usize {
+        self.file_size
+    }
+
+    fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    fn vector(&self, index: usize) -> Option<Cow<'_, [f32]>> {
+        let start = index.checked_mul(self.dimensions)?;
+        self.values.get(start..start + self.dimensions).map(Cow::Borrowed)
+    }
+}
+
+/// Writes the vectors into a `column` of fixed-size lists of `f32`.
+pub fn write_parquet(
+    file: File,
+    column: &str,
+    dimensions: usize,
+    vectors: impl Iterator<Item = anyhow::Result<Vec<f32>>>,
+) -> anyhow::Result<()> {
+    let item = Arc::new(Field::new("item", DataType::Float32, false));
+    let data_type = DataType::FixedSizeList(item.clone(), dimensions as i32);
+    let schema = Arc::new(Schema::new(vec![Field::new(column, data_type, false)]));
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+
+    let mut values = Vec::with_capacity(ROW_GROUP_SIZE * dimensions);
+    let mut flush = |values: &mut Vec<f32>| -> anyhow::Result<()> {
+        let array = FixedSizeListArray::try_new(
+            item.clone(),
+            dimensions as i32,
+            Arc::new(arrow_array::Float32Array::from(std::mem::take(values))),
+            None,
+        )?;
+        writer.write(&RecordBatch::try_new(schema.clone(), vec![Arc::new(array)])?)?;
+        Ok(())
+    };
+    for vector in vectors {
+        values.extend(vector?);
+        if values.len() == ROW_GROUP_SIZE * dimensions {
+            flush(&mut values)?;
+        }
+    }
+    if !values.is_empty() {
+        flush(&mut values)?;
+    }
+    writer.close()?;
+    Ok(())
+}
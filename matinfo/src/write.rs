@@ -0,0 +1,147 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use half::{bf16, f16};
+use matfile::{Dtype, MatHeader, NpyDtype, NpyHeader};
+
+use crate::parquet::write_parquet;
+
+/// The formats `matinfo` can write, deduced from the extension of the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mat,
+    Npy,
+    Fvecs,
+    Parquet,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> anyhow::Result<Format> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("mat") => Ok(Format::Mat),
+            Some("npy") => Ok(Format::Npy),
+            Some("fvecs") => Ok(Format::Fvecs),
+            Some("parquet") => Ok(Format::Parquet),
+            _ => bail!(
+                "cannot write {}, the output must be a `.mat`, `.npy`, `.fvecs` or `.parquet` file",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Where and how the vectors are written.
+pub struct Output {
+    pub path: PathBuf,
+    /// The name written in the header of the `.mat` files.
+    pub name: String,
+    /// The type of the values of the `.mat` and `.npy` files, the other formats only store `f32`.
+    pub dtype: Dtype,
+    /// The column of the `.parquet` files the vectors are written into.
+    pub column: String,
+}
+
+impl Output {
+    /// Writes `rows` vectors of `dimensions` into a temporary file renamed once complete.
+    pub fn write(
+        &self,
+        dimensions: usize,
+        rows: usize,
+        vectors: impl Iterator<Item = anyhow::Result<Vec<f32>>>,
+    ) -> anyhow::Result<()> {
+        let format = Format::from_path(&self.path)?;
+        ensure!(
+            self.dtype == Dtype::F32 || matches!(format, Format::Mat | Format::Npy),
+            "{} can only store f32 values",
+            self.path.display()
+        );
+
+        let mut file_name = self.path.file_name().map_or_else(OsString::new, ToOwned::to_owned);
+        file_name.push(".tmp");
+        let temporary = self.path.with_file_name(file_name);
+        let file = File::create(&temporary)
+            .with_context(|| format!("while creating {}", temporary.display()))?;
+
+        let written = match format {
+            Format::Mat => {
+                // The header is rewritten once the checksum of the vectors is known.
+                let header = MatHeader::new(&self.name, self.dtype, dimensions, rows);
+                let mut file = io::BufWriter::new(file);
+                file.write_all(&header.clone().with_checksum(0).encode())?;
+                let mut hasher = crc32fast::Hasher::new();
+                let mut bytes = Vec::new();
+                let written = write_all(vectors, |vector| {
+                    bytes.clear();
+                    encode(&vector, self.dtype, &mut bytes);
+                    hasher.update(&bytes);
+                    file.write_all(&bytes)
+                })?;
+                let mut file = file.into_inner()?;
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(&header.with_checksum(hasher.finalize()).encode())?;
+                written
+            }
+            Format::Npy => {
+                let dtype = match self.dtype {
+                    Dtype::F32 => NpyDtype::F32,
+                    Dtype::F16 => NpyDtype::F16,
+                    Dtype::BF16 => bail!("NumPy doesn't support bf16, use a `.mat` file instead"),
+                };
+                let mut file = io::BufWriter::new(file);
+                file.write_all(&NpyHeader::new(dtype, rows, dimensions).encode())?;
+                let mut bytes = Vec::new();
+                let written = write_all(vectors, |vector| {
+                    bytes.clear();
+                    encode(&vector, self.dtype, &mut bytes);
+                    file.write_all(&bytes)
+                })?;
+                file.flush()?;
+                written
+            }
+            Format::Fvecs => {
+                let mut file = io::BufWriter::new(file);
+                let written = write_all(vectors, |vector| {
+                    file.write_all(&(dimensions as i32).to_le_bytes())?;
+                    vector.iter().try_for_each(|x| file.write_all(&x.to_le_bytes()))
+                })?;
+                file.flush()?;
+                written
+            }
+            Format::Parquet => {
+                let mut written = 0;
+                let vectors = vectors.inspect(|_| written += 1);
+                write_parquet(file, &self.column, dimensions, vectors)?;
+                written
+            }
+        };
+        ensure!(written == rows, "{written} vectors were written instead of {rows}");
+
+        fs::rename(&temporary, &self.path).with_context(|| {
+            format!("while renaming {} into {}", temporary.display(), self.path.display())
+        })
+    }
+}
+
+/// Calls `write` on every vector and returns the number of vectors written.
+fn write_all(
+    vectors: impl Iterator<Item = anyhow::Result<Vec<f32>>>,
+    mut write: impl FnMut(Vec<f32>) -> io::Result<()>,
+) -> anyhow::Result<usize> {
+    let mut written = 0;
+    for vector in vectors {
+        write(vector?)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn encode(vector: &[f32], dtype: Dtype, bytes: &mut Vec<u8>) {
+    match dtype {
+        Dtype::F32 => vector.iter().for_each(|x| bytes.extend(x.to_le_bytes())),
+        Dtype::F16 => vector.iter().for_each(|&x| bytes.extend(f16::from_f32(x).to_le_bytes())),
+        Dtype::BF16 => vector.iter().for_each(|&x| bytes.extend(bf16::from_f32(x).to_le_bytes())),
+    }
+}
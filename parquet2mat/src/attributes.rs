@@ -0,0 +1,99 @@
+use arrow_array::cast::AsArray;
+use arrow_array::types::*;
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_buffer::NullBuffer;
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::DataType;
+use serde_json::{Map, Value};
+
+use crate::column::ColumnPath;
+
+/// The columns written, as JSON objects, alongside the embeddings.
+pub struct AttributeColumns {
+    /// The name of every attribute in the JSON objects, with its column and nulls.
+    columns: Vec<(String, ArrayRef, Option<NullBuffer>)>,
+}
+
+impl AttributeColumns {
+    pub fn new(
+        batch: &RecordBatch,
+        attributes: &[(String, ColumnPath)],
+    ) -> anyhow::Result<AttributeColumns> {
+        let columns = attributes
+            .iter()
+            .map(|(name, path)| {
+                let (array, nulls) = path.column(batch)?;
+                Ok((name.clone(), array, nulls))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(AttributeColumns { columns })
+    }
+
+    /// Appends the attributes of the row as a line of JSON.
+    pub fn write_row(&self, row: usize, output: &mut Vec<u8>) {
+        let object: Map<String, Value> = self
+            .columns
+            .iter()
+            .map(|(name, array, nulls)| {
+                let value = match nulls {
+                    Some(nulls) if nulls.is_null(row) => Value::Null,
+                    _ => json_value(array, row),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        serde_json::to_writer(&mut *output, &object).unwrap();
+        output.push(b'\n');
+    }
+}
+
+/// Converts a value of an Arrow array to JSON, the types without
+/// equivalent in JSON, like the dates, are written as strings.
+fn json_value(array: &ArrayRef, row: usize) -> Value {
+    macro_rules! primitive {
+        ($type:ty) => {
+            Value::from(array.as_primitive::<$type>().value(row))
+        };
+    }
+
+    if array.is_null(row) {
+        return Value::Null;
+    }
+    match array.data_type() {
+        DataType::Boolean => Value::from(array.as_boolean().value(row)),
+        DataType::Int8 => primitive!(Int8Type),
+        DataType::Int16 => primitive!(Int16Type),
+        DataType::Int32 => primitive!(Int32Type),
+        DataType::Int64 => primitive!(Int64Type),
+        DataType::UInt8 => primitive!(UInt8Type),
+        DataType::UInt16 => primitive!(UInt16Type),
+        DataType::UInt32 => primitive!(UInt32Type),
+        DataType::UInt64 => primitive!(UInt64Type),
+        DataType::Float16 => Value::from(array.as_primitive::<Float16Type>().value(row).to_f32()),
+        DataType::Float32 => primitive!(Float32Type),
+        DataType::Float64 => primitive!(Float64Type),
+        DataType::Utf8 => Value::from(array.as_string::<i32>().value(row)),
+        DataType::LargeUtf8 => Value::from(array.as_string::<i64>().value(row)),
+        DataType::List(_) => list_value(&array.as_list::<i32>().value(row)),
+        DataType::LargeList(_) => list_value(&array.as_list::<i64>().value(row)),
+        DataType::FixedSizeList(_, _) => list_value(&array.as_fixed_size_list().value(row)),
+        DataType::Struct(_) => {
+            let structure = array.as_struct();
+            let object = structure
+                .column_names()
+                .into_iter()
+                .zip(structure.columns())
+                .map(|(name, column)| (name.to_string(), json_value(column, row)))
+                .collect::<Map<_, _>>();
+            Value::Object(object)
+        }
+        _ => match ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default()) {
+            Ok(formatter) => Value::from(formatter.value(row).to_string()),
+            Err(_) => Value::Null,
+        },
+    }
+}
+
+fn list_value(values: &ArrayRef) -> Value {
+    Value::Array((0..values.len()).map(|i| json_value(values, i)).collect())
+}
@@ -0,0 +1,335 @@
+use std::ops::Range;
+
+use anyhow::bail;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float16Type, Float32Type, Float64Type, Int8Type, UInt8Type};
+use arrow_array::{
+    Array, ArrayRef, GenericBinaryArray, GenericListArray, OffsetSizeTrait, RecordBatch,
+};
+use arrow_buffer::{Buffer, NullBuffer};
+use arrow_schema::{DataType, Schema};
+use clap::ValueEnum;
+use half::{bf16, f16};
+use matfile::Dtype;
+
+/// The type of the little-endian values packed in the binary embeddings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BinaryType {
+    F32,
+    F16,
+    Bf16,
+    F64,
+    I8,
+    U8,
+}
+
+impl BinaryType {
+    fn size(self) -> usize {
+        match self {
+            BinaryType::F32 => 4,
+            BinaryType::F16 | BinaryType::Bf16 => 2,
+            BinaryType::F64 => 8,
+            BinaryType::I8 | BinaryType::U8 => 1,
+        }
+    }
+}
+
+/// How the values of the embedding column are decoded.
+#[derive(Debug, Clone, Copy)]
+pub struct Decoding {
+    pub binary_type: BinaryType,
+    /// The integer values are dequantized as `value * scale + offset`.
+    pub scale: f32,
+    pub offset: f32,
+    /// The type of the values written in the output file.
+    pub dtype: Dtype,
+}
+
+impl Decoding {
+    fn dequantize(&self, value: f32) -> f32 {
+        value * self.scale + self.offset
+    }
+}
+
+/// A column of the parquet files, possibly nested in structs.
+#[derive(Debug, Clone)]
+pub struct ColumnPath {
+    /// The name of the root column.
+    pub root: String,
+    /// The index of the root column in the schema.
+    pub index: usize,
+    /// The path of struct fields leading to the column from the root column.
+    pub fields: Vec<String>,
+}
+
+impl ColumnPath {
+    /// Finds the root column and the path of struct fields leading to the column.
+    ///
+    /// A name like `a.b.embedding` designates the `embedding` field of the `b` struct
+    /// of the `a` column, unless there is a column named `a.b.embedding`.
+    pub fn resolve(schema: &Schema, name: &str) -> Option<ColumnPath> {
+        if let Ok(index) = schema.index_of(name) {
+            return Some(ColumnPath { root: name.to_string(), index, fields: Vec::new() });
+        }
+        let mut parts = name.split('.');
+        let root = parts.next()?;
+        let index = schema.index_of(root).ok()?;
+        let mut data_type = schema.field(index).data_type();
+        let mut fields = Vec::new();
+        for part in parts {
+            let DataType::Struct(children) = data_type else { return None };
+            let (_, child) = children.find(part)?;
+            data_type = child.data_type();
+            fields.push(part.to_string());
+        }
+        Some(ColumnPath { root: root.to_string(), index, fields })
+    }
+
+    /// Follows the struct fields from the root column of the batch, a row is null if
+    /// any of its parents is null. Returns the column with its nulls.
+    pub fn column(&self, batch: &RecordBatch) -> anyhow::Result<(ArrayRef, Option<NullBuffer>)> {
+        let Some(mut array) = batch.column_by_name(&self.root).cloned() else {
+            bail!("there is no `{}` column", self.root);
+        };
+        let mut nulls = None;
+        for field in &self.fields {
+            nulls = NullBuffer::union(nulls.as_ref(), array.logical_nulls().as_ref());
+            let Some(child) = array.as_struct_opt().and_then(|s| s.column_by_name(field)) else {
+                bail!("there is no `{field}` field in the {} column", array.data_type());
+            };
+            array = child.clone();
+        }
+        let nulls = NullBuffer::union(nulls.as_ref(), array.logical_nulls().as_ref());
+        Ok((array, nulls))
+    }
+}
+
+/// Why an embedding can't be written in the output file.
+pub enum InvalidEmbedding {
+    Null,
+    /// The embedding doesn't have the expected number of dimensions, with a description of its size.
+    Dimensions(String),
+    NullValues,
+}
+
+/// The embeddings of a record batch, whatever their encoding.
+pub struct EmbeddingColumn {
+    values: Values,
+    /// The range of the values of every row, `None` when the row is null.
+    rows: Vec<Option<Range<usize>>>,
+}
+
+/// The flat values of every embedding of the column.
+enum Values {
+    /// The values of a list, indexed by value.
+    Array(ArrayRef),
+    /// Binary blobs, indexed by byte.
+    Binary(Buffer, BinaryType),
+}
+
+impl EmbeddingColumn {
+    pub fn new(
+        batch: &RecordBatch,
+        path: &ColumnPath,
+        binary_type: BinaryType,
+    ) -> anyhow::Result<EmbeddingColumn> {
+        let (array, nulls) = path.column(batch)?;
+
+        let (values, ranges) = match array.data_type() {
+            DataType::List(_) => list_ranges(array.as_list::<i32>()),
+            DataType::LargeList(_) => list_ranges(array.as_list::<i64>()),
+            DataType::FixedSizeList(_, _) => {
+                let list = array.as_fixed_size_list();
+                let length = list.value_length() as usize;
+                let ranges = (0..list.len())
+                    .map(|row| {
+                        let start = list.value_offset(row) as usize;
+                        start..start + length
+                    })
+                    .collect();
+                (Values::Array(list.values().clone()), ranges)
+            }
+            DataType::Binary => binary_ranges(array.as_binary::<i32>(), binary_type),
+            DataType::LargeBinary => binary_ranges(array.as_binary::<i64>(), binary_type),
+            DataType::FixedSizeBinary(_) => {
+                let binary = array.as_fixed_size_binary();
+                let length = binary.value_length() as usize;
+                let ranges = (0..binary.len())
+                    .map(|row| {
+                        let start = binary.value_offset(row) as usize;
+                        start..start + length
+                    })
+                    .collect();
+                (Values::Binary(binary.values().clone(), binary_type), ranges)
+            }
+            data_type => bail!("this is not a list or a binary but a {data_type}"),
+        };
+        if let Values::Array(values) = &values {
+            match values.data_type() {
+                DataType::Float16
+                | DataType::Float32
+                | DataType::Float64
+                | DataType::Int8
+                | DataType::UInt8 => (),
+                data_type => {
+                    bail!("this is not a list of floats or bytes but a list of {data_type}")
+                }
+            }
+        }
+
+        let rows = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(row, range)| nulls.as_ref().is_none_or(|n| n.is_valid(row)).then_some(range))
+            .collect();
+        Ok(EmbeddingColumn { values, rows })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The number of bytes of every value, the values of the lists are indexed one by one.
+    fn width(&self) -> usize {
+        match self.values {
+            Values::Array(_) => 1,
+            Values::Binary(_, binary_type) => binary_type.size(),
+        }
+    }
+
+    /// The number of dimensions of the first non-null and non-empty embedding, if any.
+    pub fn first_dimensions(&self) -> Option<usize> {
+        let width = self.width();
+        self.rows
+            .iter()
+            .flatten()
+            .find(|range| !range.is_empty() && range.len() % width == 0)
+            .map(|range| range.len() / width)
+    }
+
+    /// Returns the range of the values of the embedding if it is valid.
+    pub fn check(&self, row: usize, dimensions: usize) -> Result<Range<usize>, InvalidEmbedding> {
+        let Some(range) = self.rows[row].clone() else {
+            return Err(InvalidEmbedding::Null);
+        };
+        match &self.values {
+            Values::Array(values) => {
+                if range.len() != dimensions {
+                    return Err(InvalidEmbedding::Dimensions(format!(
+                        "has {} dimensions instead of {dimensions}",
+                        range.len()
+                    )));
+                }
+                if values.null_count() != 0 && range.clone().any(|i| values.is_null(i)) {
+                    return Err(InvalidEmbedding::NullValues);
+                }
+            }
+            Values::Binary(_, binary_type) => {
+                if range.len() != dimensions * binary_type.size() {
+                    return Err(InvalidEmbedding::Dimensions(format!(
+                        "has {} bytes instead of {} for {dimensions} {} values",
+                        range.len(),
+                        dimensions * binary_type.size(),
+                        binary_type.to_possible_value().unwrap().get_name(),
+                    )));
+                }
+            }
+        }
+        Ok(range)
+    }
+
+    /// Appends the values in `range` to `bytes`, encoded in the output type.
+    pub fn encode(&self, range: Range<usize>, decoding: &Decoding, bytes: &mut Vec<u8>) {
+        let dtype = decoding.dtype;
+        match &self.values {
+            Values::Array(values) => {
+                let values = values.slice(range.start, range.len());
+                match values.data_type() {
+                    DataType::Float16 => {
+                        let values = values.as_primitive::<Float16Type>().values();
+                        encode(values.iter().map(|half| half.to_f32()), dtype, bytes)
+                    }
+                    DataType::Float32 => {
+                        let values = values.as_primitive::<Float32Type>().values();
+                        match dtype {
+                            Dtype::F32 => bytes.extend_from_slice(bytemuck::cast_slice(values)),
+                            _ => encode(values.iter().copied(), dtype, bytes),
+                        }
+                    }
+                    DataType::Float64 => {
+                        let values = values.as_primitive::<Float64Type>().values();
+                        encode(values.iter().map(|&double| double as f32), dtype, bytes)
+                    }
+                    DataType::Int8 => {
+                        let values = values.as_primitive::<Int8Type>().values();
+                        let floats = values.iter().map(|&x| decoding.dequantize(x as f32));
+                        encode(floats, dtype, bytes)
+                    }
+                    DataType::UInt8 => {
+                        let values = values.as_primitive::<UInt8Type>().values();
+                        let floats = values.iter().map(|&x| decoding.dequantize(x as f32));
+                        encode(floats, dtype, bytes)
+                    }
+                    _ => unreachable!("the type of the values is checked when creating the column"),
+                }
+            }
+            Values::Binary(buffer, binary_type) => {
+                let blob = &buffer[range];
+                let chunks = blob.chunks_exact(binary_type.size());
+                match binary_type {
+                    BinaryType::F32 if dtype == Dtype::F32 => bytes.extend_from_slice(blob),
+                    BinaryType::F32 => encode(
+                        chunks.map(|c| f32::from_le_bytes(c.try_into().unwrap())),
+                        dtype,
+                        bytes,
+                    ),
+                    BinaryType::F16 => encode(
+                        chunks.map(|c| f16::from_le_bytes(c.try_into().unwrap()).to_f32()),
+                        dtype,
+                        bytes,
+                    ),
+                    BinaryType::Bf16 => encode(
+                        chunks.map(|c| bf16::from_le_bytes(c.try_into().unwrap()).to_f32()),
+                        dtype,
+                        bytes,
+                    ),
+                    BinaryType::F64 => encode(
+                        chunks.map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32),
+                        dtype,
+                        bytes,
+                    ),
+                    BinaryType::I8 => encode(
+                        blob.iter().map(|&x| decoding.dequantize(x as i8 as f32)),
+                        dtype,
+                        bytes,
+                    ),
+                    BinaryType::U8 => {
+                        encode(blob.iter().map(|&x| decoding.dequantize(x as f32)), dtype, bytes)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn list_ranges<O: OffsetSizeTrait>(list: &GenericListArray<O>) -> (Values, Vec<Range<usize>>) {
+    let ranges = list.value_offsets().windows(2).map(|w| w[0].as_usize()..w[1].as_usize());
+    (Values::Array(list.values().clone()), ranges.collect())
+}
+
+fn binary_ranges<O: OffsetSizeTrait>(
+    binary: &GenericBinaryArray<O>,
+    binary_type: BinaryType,
+) -> (Values, Vec<Range<usize>>) {
+    let ranges = binary.value_offsets().windows(2).map(|w| w[0].as_usize()..w[1].as_usize());
+    (Values::Binary(binary.values().clone(), binary_type), ranges.collect())
+}
+
+fn encode(floats: impl ExactSizeIterator<Item = f32>, dtype: Dtype, bytes: &mut Vec<u8>) {
+    bytes.reserve(floats.len() * dtype.size());
+    match dtype {
+        Dtype::F32 => floats.for_each(|float| bytes.extend(float.to_le_bytes())),
+        Dtype::F16 => floats.for_each(|float| bytes.extend(f16::from_f32(float).to_le_bytes())),
+        Dtype::BF16 => floats.for_each(|float| bytes.extend(bf16::from_f32(float).to_le_bytes())),
+    }
+}
@@ -1,11 +1,25 @@
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::fmt;
+use std::fs::{self, File};
+use std::iter;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-use anyhow::{bail, Context};
-use clap::Parser;
-use parquet::file::reader::{FileReader, SerializedFileReader};
-use parquet::record::{Field, Row};
+use anyhow::{bail, ensure, Context};
+use attributes::AttributeColumns;
+use clap::{Parser, ValueEnum};
+use column::{BinaryType, ColumnPath, Decoding, EmbeddingColumn, InvalidEmbedding};
+use matfile::{Dtype, MatHeader, NpyDtype, NpyHeader, ShardManifest};
+use output::{Header, MatrixWriter, Progress};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ProjectionMask;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use selection::Selection;
+
+mod attributes;
+mod column;
+mod output;
+mod selection;
 
 /// Small tool that takes mltiple parquet files and extracts the embeddings to output them flat in a matrix file.
 #[derive(Parser)]
@@ -13,79 +27,498 @@ struct Args {
     /// The set of parquet files to ouput in the output file.
     files: Vec<PathBuf>,
 
-    /// The name of the embedding field to extract.
+    /// The name of the embedding field to extract, `a.b.embedding` for a field nested in structs.
+    ///
+    /// It can be a list or a fixed-size list of floats, int8 or uint8, or a binary blob of little-endian values.
     #[arg(long)]
     embedding_name: String,
 
+    /// The type of the little-endian values of the binary embeddings.
+    #[arg(long, value_enum, default_value_t = BinaryType::F32)]
+    binary_type: BinaryType,
+
+    /// The int8 and uint8 values are dequantized as `value * scale + offset`.
+    #[arg(long, default_value_t = 1.0)]
+    scale: f32,
+
+    /// The int8 and uint8 values are dequantized as `value * scale + offset`.
+    #[arg(long, default_value_t = 0.0)]
+    offset: f32,
+
     /// The output file name.
+    ///
+    /// A NumPy array of shape `(rows, dimensions)` is written instead of a `.mat` file
+    /// if it ends with `.npy`.
     #[arg(long, default_value = "output.mat")]
     output: PathBuf,
+
+    /// The type of the values written in the output file: `f32`, `f16` or `bf16`.
+    ///
+    /// The half precision types halve the size of the file, `bf16` can only be written in a `.mat` file.
+    #[arg(long, default_value = "f32")]
+    dtype: Dtype,
+
+    /// The name of the dataset written in the header of the `.mat` file, the output file name by default.
+    #[arg(long)]
+    name: Option<String>,
+
+    /// The number of dimensions of the embeddings, the one of the first embedding by default.
+    ///
+    /// Every embedding is checked against it, so that a malformed row doesn't shift the following ones.
+    #[arg(long)]
+    dimensions: Option<usize>,
+
+    /// What to do with the embeddings that are null, contain nulls or don't have the expected
+    /// number of dimensions.
+    #[arg(long, value_enum, default_value_t = InvalidRows::Reject)]
+    invalid_rows: InvalidRows,
+
+    /// The column identifying the documents, written as `id` in the attributes file.
+    #[arg(long)]
+    id_column: Option<String>,
+
+    /// The columns written in the attributes file, a JSON object per embedding.
+    #[arg(long, value_delimiter = ',')]
+    attribute_columns: Vec<String>,
+
+    /// The JSON Lines file the ids and attributes are written into, aligned line by line with
+    /// the embeddings of the output file. It is the output file with the `.attributes.jsonl`
+    /// extension by default, where the benchmarks look for it.
+    #[arg(long)]
+    attributes_output: Option<PathBuf>,
+
+    /// Only writes the first embeddings, the remaining row groups are not read.
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Writes a uniform random sample of this many embeddings, among the `--limit` first ones
+    /// if specified, in the order they were read. The sample is kept in memory until the end.
+    #[arg(long)]
+    sample: Option<usize>,
+
+    /// The seed of the random generator picking the `--sample` embeddings.
+    #[arg(long, default_value_t = 38, requires = "sample")]
+    seed: u64,
+
+    /// Skips the embeddings identical to one already written, once encoded in the output type.
+    #[arg(long)]
+    deduplicate: bool,
+
+    /// Splits the output into numbered files of at most this many embeddings, e.g.
+    /// `embeddings-00000.mat`, described by a manifest, e.g. `embeddings.shards.toml`.
+    ///
+    /// The benchmarks read the manifest as a single matrix.
+    #[arg(long)]
+    shard_size: Option<NonZeroUsize>,
+
+    /// Resumes an interrupted conversion after the last input file entirely converted.
+    ///
+    /// The files are written under a `.tmp` name and renamed once complete, the progress of the
+    /// conversion is recorded next to the output, e.g. in `output.mat.progress.toml`.
+    #[arg(long, conflicts_with_all = ["sample", "deduplicate"])]
+    resume: bool,
 }
 
 fn main() -> anyhow::Result<()> {
-    let Args { files, embedding_name, output: output_path } = Args::parse();
+    let Args {
+        files,
+        embedding_name,
+        binary_type,
+        scale,
+        offset,
+        output: output_path,
+        dtype,
+        name,
+        dimensions,
+        invalid_rows,
+        id_column,
+        attribute_columns,
+        attributes_output,
+        limit,
+        sample,
+        seed,
+        deduplicate,
+        shard_size,
+        resume,
+    } = Args::parse();
+    let decoding = Decoding { binary_type, scale, offset, dtype };
+    let name = match name {
+        Some(name) => name,
+        None => output_path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into()),
+    };
 
-    let output = File::create(&output_path)
-        .with_context(|| format!("while opening {}", output_path.display()))?;
-    let mut output = io::BufWriter::new(output);
-    let mut total_embeddings_count: usize = 0;
-    let mut guessed_dimensions = None;
+    let header = match output_path.extension() {
+        Some(extension) if extension == "npy" => {
+            let dtype = match dtype {
+                Dtype::F32 => NpyDtype::F32,
+                Dtype::F16 => NpyDtype::F16,
+                Dtype::BF16 => bail!("NumPy doesn't support bf16, use a `.mat` file instead"),
+            };
+            Header::Npy(NpyHeader::new(dtype, 0, 0))
+        }
+        _ => Header::Mat(MatHeader::new(name.clone(), dtype, 0, 0)),
+    };
+    let mut total_skipped = Skipped::default();
 
-    for file_path in files {
-        let file = File::open(&file_path)?;
-        let reader = SerializedFileReader::new(file)
-            .with_context(|| format!("while opening {}", file_path.display()))?;
+    // The sample and the deduplicated embeddings are only known in memory, they can't be resumed.
+    let progress_path = Progress::path(&output_path);
+    let checkpoints = sample.is_none() && !deduplicate;
+    let progress = match (resume, progress_path.exists()) {
+        (true, true) => {
+            let progress = Progress::read_from_path(&progress_path)?;
+            ensure!(
+                files.starts_with(&progress.files),
+                "the conversion to resume was started with other files, {} were already converted",
+                progress
+                    .files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            ensure!(
+                progress.name == name
+                    && progress.dtype == dtype.to_string()
+                    && progress.shard_size == shard_size.map(NonZeroUsize::get)
+                    && dimensions.is_none_or(|dimensions| dimensions == progress.dimensions),
+                "the conversion to resume was started with other options, see {}",
+                progress_path.display()
+            );
+            println!(
+                "resuming the conversion after {} files and {} embeddings.",
+                progress.files.len(),
+                progress.kept
+            );
+            Some(progress)
+        }
+        (true, false) => {
+            println!("there is no conversion to resume, starting from the first file.");
+            None
+        }
+        (false, true) => {
+            println!("starting over the interrupted conversion, use `--resume` to resume it.");
+            None
+        }
+        (false, false) => None,
+    };
+    let converted_files = progress.as_ref().map_or(0, |progress| progress.files.len());
+    let dimensions = dimensions.or(progress.as_ref().map(|progress| progress.dimensions));
 
-        let mut embeddings_count = 0;
-        for result in reader
-            .get_row_iter(None)
-            .with_context(|| format!("while starting the iteration on {}", file_path.display()))?
-        {
-            let row: Row =
-                result.with_context(|| format!("while iterating on {}", file_path.display()))?;
-            for (name, field) in row.get_column_iter() {
-                if name == &embedding_name {
-                    embeddings_count += 1;
-                    let list = match field {
-                        Field::ListInternal(list) => list,
-                        _ => bail!("this is not a list while processing {}", file_path.display()),
-                    };
-
-                    let mut floats = Vec::with_capacity(list.len());
-                    guessed_dimensions = match guessed_dimensions {
-                        Some(x) => Some(x),
-                        None => {
-                            println!("the embeddings are of {} dimensions", list.len());
-                            Some(list.len())
-                        }
-                    };
-                    for element in list.elements() {
-                        match element {
-                            Field::Float16(f16) => floats.push((*f16).into()),
-                            Field::Float(simple) => floats.push(*simple),
-                            Field::Double(double) => floats.push(*double as f32),
-                            _ => bail!(
-                                "this is not a list of doubles while processing {}",
-                                file_path.display()
-                            ),
-                        }
-                    }
+    let mut row_groups = Vec::new();
+    for file_path in &files[converted_files..] {
+        let reader = File::open(file_path)
+            .with_context(|| format!("while opening {}", file_path.display()))?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(reader)
+            .with_context(|| format!("while opening {}", file_path.display()))?;
+        let resolve = |name: &str| {
+            ColumnPath::resolve(builder.schema(), name)
+                .with_context(|| format!("there is no `{name}` column in {}", file_path.display()))
+        };
+        let embedding = resolve(&embedding_name)?;
+        let mut attributes = Vec::new();
+        if let Some(id_column) = &id_column {
+            attributes.push(("id".to_string(), resolve(id_column)?));
+        }
+        for name in &attribute_columns {
+            attributes.push((name.clone(), resolve(name)?));
+        }
+        let count = builder.metadata().num_row_groups();
+        let mut first_row = 0;
+        for index in 0..count {
+            row_groups.push(RowGroup {
+                path: file_path,
+                index,
+                first_row,
+                embedding: embedding.clone(),
+                attributes: attributes.clone(),
+                last: index + 1 == count,
+            });
+            first_row += builder.metadata().row_group(index).num_rows() as usize;
+        }
+    }
 
-                    output.write_all(bytemuck::cast_slice(&floats))?;
+    let dimensions = match dimensions {
+        Some(dimensions) => dimensions,
+        None => {
+            let mut guessed_dimensions = None;
+            for row_group in &row_groups {
+                guessed_dimensions =
+                    row_group.first_dimensions(binary_type).with_context(|| row_group.context())?;
+                if guessed_dimensions.is_some() {
+                    break;
                 }
             }
+            let Some(dimensions) = guessed_dimensions else {
+                bail!("no embeddings were found in the parquet files");
+            };
+            println!("the embeddings are of {} dimensions", dimensions);
+            dimensions
         }
+    };
+
+    // The number of vectors is only known at the end, the writer reserves
+    // room for the headers and rewrites them once the files are complete.
+    let attributes_output = match (&id_column, attribute_columns.is_empty()) {
+        (None, true) => None,
+        // The attributes are named after the manifest when sharding, where the benchmarks look for them.
+        _ => Some(attributes_output.unwrap_or_else(|| {
+            match shard_size {
+                Some(_) => output_path.with_extension(ShardManifest::EXTENSION),
+                None => output_path.clone(),
+            }
+            .with_extension("attributes.jsonl")
+        })),
+    };
+    let vector_size = dimensions * dtype.size();
+    let mut writer = MatrixWriter::new(
+        output_path,
+        name,
+        header,
+        dimensions,
+        shard_size.map(NonZeroUsize::get),
+        attributes_output,
+        progress.as_ref(),
+    )?;
+    let mut selection = Selection::new(limit, sample, seed, deduplicate);
+    selection.kept = progress.as_ref().map_or(0, |progress| progress.kept);
+    let mut converted_files = files[..converted_files].to_vec();
+
+    // The row groups are converted in parallel, a batch of them at a time to bound
+    // the memory usage, and appended to the output in the order of the files.
+    let mut embeddings_count = 0;
+    let mut skipped = Skipped::default();
+    'row_groups: for batch in row_groups.chunks(rayon::current_num_threads()) {
+        let converted: Vec<_> = batch
+            .par_iter()
+            .map(|row_group| row_group.convert(dimensions, invalid_rows, &decoding))
+            .collect();
+        for (row_group, embeddings) in batch.iter().zip(converted) {
+            let embeddings = embeddings.with_context(|| row_group.context())?;
+            let mut attributes = embeddings.attributes.split_inclusive(|&byte| byte == b'\n');
+            for vector in embeddings.bytes.chunks_exact(vector_size) {
+                selection.push(vector, attributes.next().unwrap_or_default(), &mut writer)?;
+            }
+            embeddings_count += embeddings.rows;
+            skipped.add(embeddings.skipped);
+
+            if selection.is_full() {
+                println!("stopped after the first {} embeddings.", selection.kept);
+                total_skipped.add(skipped);
+                break 'row_groups;
+            }
+            if row_group.last {
+                if skipped.total() == 0 {
+                    println!("{} embeddings appended to the output.", embeddings_count);
+                } else {
+                    println!(
+                        "{} embeddings appended to the output, {} of {}.",
+                        embeddings_count,
+                        skipped,
+                        row_group.path.display()
+                    );
+                }
+                total_skipped.add(skipped);
+                embeddings_count = 0;
+                skipped = Skipped::default();
 
-        println!("{} embeddings appended to the output.", embeddings_count);
-        total_embeddings_count += embeddings_count;
+                converted_files.push(row_group.path.to_path_buf());
+                if checkpoints {
+                    let progress = writer.checkpoint(converted_files.clone(), selection.kept)?;
+                    progress.write_to_path(&progress_path)?;
+                }
+            }
+        }
     }
 
-    output.flush()?;
-    println!(
-        "done appending {} embeddings into {}.",
-        total_embeddings_count,
-        output_path.display()
-    );
+    if selection.kept == 0 {
+        bail!("no embeddings were found in the parquet files");
+    }
+    if selection.duplicates != 0 {
+        println!("{} duplicate embeddings removed.", selection.duplicates);
+    }
+    if let Some(sample) = sample {
+        println!("{} embeddings sampled out of {}.", sample.min(selection.kept), selection.kept);
+    }
+    selection.finish(&mut writer)?;
+    let written = writer.finish()?;
+    if progress_path.exists() {
+        fs::remove_file(&progress_path)?;
+    }
+    if shard_size.is_some() {
+        println!(
+            "done appending {} embeddings into {} shards described by {}.",
+            written.rows,
+            written.shards,
+            written.path.display()
+        );
+    } else {
+        println!("done appending {} embeddings into {}.", written.rows, written.path.display());
+    }
+    if total_skipped.total() != 0 {
+        println!("{} in total.", total_skipped);
+    }
+    if let Some(path) = written.attributes {
+        println!("the attributes of the embeddings were written into {}.", path.display());
+    }
 
     Ok(())
 }
+
+/// What to do with the rows whose embedding is null, contains nulls or doesn't
+/// have the expected number of dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InvalidRows {
+    /// Stops the conversion at the first invalid row.
+    Reject,
+    /// Skips the invalid rows and reports how many were skipped in every file.
+    Skip,
+}
+
+/// A row group of a parquet file, the unit of work converted by a thread.
+struct RowGroup<'a> {
+    path: &'a Path,
+    index: usize,
+    /// The index in the file of the first row of the row group.
+    first_row: usize,
+    embedding: ColumnPath,
+    /// The columns written in the attributes file, with their name.
+    attributes: Vec<(String, ColumnPath)>,
+    /// Whether it is the last row group of the file.
+    last: bool,
+}
+
+/// The embeddings of a row group, already encoded in the output type.
+struct Embeddings {
+    /// The number of embeddings written.
+    rows: usize,
+    skipped: Skipped,
+    bytes: Vec<u8>,
+    /// The attributes of the embeddings written, one JSON object per line.
+    attributes: Vec<u8>,
+}
+
+/// The number of rows skipped for each reason.
+#[derive(Debug, Default, Clone, Copy)]
+struct Skipped {
+    null: usize,
+    dimensions: usize,
+    null_values: usize,
+}
+
+impl Skipped {
+    fn total(&self) -> usize {
+        self.null + self.dimensions + self.null_values
+    }
+
+    fn add(&mut self, other: Skipped) {
+        self.null += other.null;
+        self.dimensions += other.dimensions;
+        self.null_values += other.null_values;
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows skipped ({} null, {} of another dimension, {} containing nulls)",
+            self.total(),
+            self.null,
+            self.dimensions,
+            self.null_values
+        )
+    }
+}
+
+impl RowGroup<'_> {
+    fn context(&self) -> String {
+        format!("while processing the row group {} of {}", self.index, self.path.display())
+    }
+
+    /// Reads the embedding and attribute columns only, as Arrow record batches.
+    fn batches(&self) -> anyhow::Result<ParquetRecordBatchReader> {
+        let file = File::open(self.path)?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        let roots = iter::once(&self.embedding).chain(self.attributes.iter().map(|(_, path)| path));
+        let mask = ProjectionMask::roots(builder.parquet_schema(), roots.map(|path| path.index));
+        Ok(builder.with_row_groups(vec![self.index]).with_projection(mask).build()?)
+    }
+
+    /// The number of dimensions of the first non-null and non-empty embedding, if any.
+    fn first_dimensions(&self, binary_type: BinaryType) -> anyhow::Result<Option<usize>> {
+        for batch in self.batches()? {
+            let column = EmbeddingColumn::new(&batch?, &self.embedding, binary_type)?;
+            if let Some(dimensions) = column.first_dimensions() {
+                return Ok(Some(dimensions));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Copies the values of the valid embeddings into the output type,
+    /// the consecutive valid embeddings are copied at once.
+    fn convert(
+        &self,
+        dimensions: usize,
+        invalid_rows: InvalidRows,
+        decoding: &Decoding,
+    ) -> anyhow::Result<Embeddings> {
+        let mut embeddings = Embeddings {
+            rows: 0,
+            skipped: Skipped::default(),
+            bytes: Vec::new(),
+            attributes: Vec::new(),
+        };
+        let mut read = 0;
+        for batch in self.batches()? {
+            let batch = batch?;
+            let column = EmbeddingColumn::new(&batch, &self.embedding, decoding.binary_type)?;
+            let attributes = (!self.attributes.is_empty())
+                .then(|| AttributeColumns::new(&batch, &self.attributes))
+                .transpose()?;
+            let mut valid: Option<Range<usize>> = None;
+            for row in 0..column.len() {
+                let problem = match column.check(row, dimensions) {
+                    Ok(range) => {
+                        embeddings.rows += 1;
+                        if let Some(attributes) = &attributes {
+                            attributes.write_row(row, &mut embeddings.attributes);
+                        }
+                        valid = match valid {
+                            Some(valid) if valid.end == range.start => Some(valid.start..range.end),
+                            Some(valid) => {
+                                column.encode(valid, decoding, &mut embeddings.bytes);
+                                Some(range)
+                            }
+                            None => Some(range),
+                        };
+                        continue;
+                    }
+                    Err(InvalidEmbedding::Null) => {
+                        embeddings.skipped.null += 1;
+                        "is null".to_string()
+                    }
+                    Err(InvalidEmbedding::Dimensions(problem)) => {
+                        embeddings.skipped.dimensions += 1;
+                        problem
+                    }
+                    Err(InvalidEmbedding::NullValues) => {
+                        embeddings.skipped.null_values += 1;
+                        "contains null values".to_string()
+                    }
+                };
+                if invalid_rows == InvalidRows::Reject {
+                    bail!(
+                        "the embedding of the row {} {problem}, use `--invalid-rows skip` to skip it",
+                        self.first_row + read + row
+                    );
+                }
+            }
+            if let Some(valid) = valid {
+                column.encode(valid, decoding, &mut embeddings.bytes);
+            }
+            read += column.len();
+        }
+        Ok(embeddings)
+    }
+}
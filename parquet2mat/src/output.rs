@@ -0,0 +1,362 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use matfile::{MatHeader, NpyHeader, Shard, ShardManifest};
+use serde::{Deserialize, Serialize};
+
+/// The header of the output files, rewritten once all the embeddings of a file are appended.
+#[derive(Clone)]
+pub enum Header {
+    Mat(MatHeader),
+    Npy(NpyHeader),
+}
+
+impl Header {
+    fn set_shape(&mut self, rows: usize, dimensions: usize) {
+        match self {
+            Header::Mat(header) => {
+                header.rows = rows;
+                header.dimensions = dimensions;
+            }
+            Header::Npy(header) => {
+                header.rows = rows;
+                header.dimensions = dimensions;
+            }
+        }
+    }
+
+    /// Records the checksum of the vectors, the NumPy files have no room for it.
+    fn set_checksum(&mut self, checksum: u32) {
+        if let Header::Mat(header) = self {
+            *header = header.clone().with_checksum(checksum);
+        }
+    }
+
+    /// The size in bytes of `rows` vectors.
+    fn data_len(&self, rows: usize) -> usize {
+        match self {
+            Header::Mat(header) => rows * header.dimensions * header.dtype.size(),
+            Header::Npy(header) => rows * header.dimensions * header.dtype.size(),
+        }
+    }
+
+    /// Encodes the header, its length doesn't depend on the shape nor on the value of the checksum.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Header::Mat(header) => header.encode(),
+            Header::Npy(header) => header.encode(),
+        }
+    }
+}
+
+/// The temporary file a file is written into before being renamed once complete,
+/// so that an interrupted conversion never leaves a truncated file behind.
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map_or_else(OsString::new, ToOwned::to_owned);
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+/// Writes `content` into `path` through a temporary file renamed once written.
+fn write_atomically(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let temporary = temporary_path(path);
+    let mut file = File::create(&temporary)
+        .with_context(|| format!("while opening {}", temporary.display()))?;
+    file.write_all(content)?;
+    file.sync_data()?;
+    fs::rename(&temporary, path)
+        .with_context(|| format!("while renaming {} into {}", temporary.display(), path.display()))
+}
+
+/// The state of an interrupted conversion, written next to the output after every
+/// input file converted, from which the conversion can be resumed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Progress {
+    /// The options the conversion must be resumed with.
+    pub name: String,
+    pub dtype: String,
+    pub dimensions: usize,
+    pub shard_size: Option<usize>,
+    /// The input files entirely converted, in order.
+    pub files: Vec<PathBuf>,
+    /// The number of embeddings written, see [`crate::selection::Selection::kept`].
+    pub kept: usize,
+    /// The shards already complete and renamed.
+    pub shards: Vec<Shard>,
+    /// The number of embeddings of the file being written.
+    pub rows: usize,
+    /// The running CRC32 of the embeddings of the file being written.
+    pub checksum: u32,
+    /// The length of the attributes file.
+    pub attributes_len: u64,
+}
+
+impl Progress {
+    /// The file the progress of the conversion into `output` is recorded in.
+    pub fn path(output: &Path) -> PathBuf {
+        let mut file_name = output.file_name().map_or_else(OsString::new, ToOwned::to_owned);
+        file_name.push(".progress.toml");
+        output.with_file_name(file_name)
+    }
+
+    pub fn read_from_path(path: &Path) -> anyhow::Result<Progress> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("while reading {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("while parsing {}", path.display()))
+    }
+
+    pub fn write_to_path(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomically(path, toml::to_string(self)?.as_bytes())
+    }
+}
+
+/// A file being written and the number of embeddings appended to it.
+struct OutputFile {
+    /// The path the file will be renamed to once complete.
+    path: PathBuf,
+    file: io::BufWriter<File>,
+    rows: usize,
+    checksum: crc32fast::Hasher,
+}
+
+/// Writes the embeddings into the output file, or into numbered shards of at most
+/// `shard_size` embeddings described by a [`ShardManifest`], and their attributes alongside.
+///
+/// Every file is written into a [`temporary_path`] and renamed once complete.
+pub struct MatrixWriter {
+    /// The output file, the shards are named after it.
+    path: PathBuf,
+    name: String,
+    header: Header,
+    dimensions: usize,
+    shard_size: Option<usize>,
+    current: Option<OutputFile>,
+    shards: Vec<Shard>,
+    attributes: Option<(PathBuf, io::BufWriter<File>, u64)>,
+}
+
+/// What was written by a [`MatrixWriter`].
+pub struct Written {
+    pub rows: usize,
+    /// The output file, or the manifest of the shards.
+    pub path: PathBuf,
+    pub shards: usize,
+    pub attributes: Option<PathBuf>,
+}
+
+impl MatrixWriter {
+    /// Starts writing the output, from scratch or from the `progress` of an interrupted conversion.
+    pub fn new(
+        path: PathBuf,
+        name: String,
+        header: Header,
+        dimensions: usize,
+        shard_size: Option<usize>,
+        attributes: Option<PathBuf>,
+        progress: Option<&Progress>,
+    ) -> anyhow::Result<MatrixWriter> {
+        // The header is reserved with a checksum, its actual value is only known at the end.
+        let mut header = header;
+        header.set_shape(0, dimensions);
+        header.set_checksum(0);
+        let attributes = match attributes {
+            Some(path) => {
+                let temporary = temporary_path(&path);
+                let len = progress.map_or(0, |progress| progress.attributes_len);
+                let file = reopen(&temporary, len)?;
+                Some((path, io::BufWriter::new(file), len))
+            }
+            None => None,
+        };
+        let mut writer = MatrixWriter {
+            path,
+            name,
+            header,
+            dimensions,
+            shard_size,
+            current: None,
+            shards: Vec::new(),
+            attributes,
+        };
+        if let Some(progress) = progress {
+            writer.shards = progress.shards.clone();
+            if progress.rows != 0 {
+                let path = writer.next_file_path();
+                let temporary = temporary_path(&path);
+                // The file may have been completed and renamed after the last checkpoint,
+                // it is resumed like the others and its extra embeddings are dropped.
+                if !temporary.exists() && path.exists() {
+                    fs::rename(&path, &temporary).with_context(|| {
+                        format!("while renaming {} into {}", path.display(), temporary.display())
+                    })?;
+                }
+                let len = writer.header.encode().len() + writer.header.data_len(progress.rows);
+                let file = reopen(&temporary, len as u64)?;
+                writer.current = Some(OutputFile {
+                    path,
+                    file: io::BufWriter::new(file),
+                    rows: progress.rows,
+                    checksum: crc32fast::Hasher::new_with_initial(progress.checksum),
+                });
+            }
+        }
+        Ok(writer)
+    }
+
+    /// The manifest written, instead of the output file, when the embeddings are sharded.
+    pub fn manifest_path(&self) -> PathBuf {
+        self.path.with_extension(ShardManifest::EXTENSION)
+    }
+
+    /// Appends an encoded embedding and the line of its attributes.
+    pub fn write(&mut self, vector: &[u8], attributes: &[u8]) -> anyhow::Result<()> {
+        let current = match &mut self.current {
+            Some(current) => current,
+            None => self.current.insert(self.create_file()?),
+        };
+        current.file.write_all(vector)?;
+        current.checksum.update(vector);
+        current.rows += 1;
+        let full = self.shard_size == Some(current.rows);
+        if let Some((_, file, len)) = &mut self.attributes {
+            file.write_all(attributes)?;
+            *len += attributes.len() as u64;
+        }
+        if full {
+            self.finish_file()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes everything written to the disk and returns the state to resume from.
+    pub fn checkpoint(&mut self, files: Vec<PathBuf>, kept: usize) -> anyhow::Result<Progress> {
+        if let Some(current) = &mut self.current {
+            current.file.flush()?;
+            current.file.get_ref().sync_data()?;
+        }
+        if let Some((_, file, _)) = &mut self.attributes {
+            file.flush()?;
+            file.get_ref().sync_data()?;
+        }
+        let dtype = match &self.header {
+            Header::Mat(header) => header.dtype.to_string(),
+            Header::Npy(header) => header.dtype.to_string(),
+        };
+        Ok(Progress {
+            name: self.name.clone(),
+            dtype,
+            dimensions: self.dimensions,
+            shard_size: self.shard_size,
+            files,
+            kept,
+            shards: self.shards.clone(),
+            rows: self.current.as_ref().map_or(0, |current| current.rows),
+            checksum: self
+                .current
+                .as_ref()
+                .map_or(0, |current| current.checksum.clone().finalize()),
+            attributes_len: self.attributes.as_ref().map_or(0, |(_, _, len)| *len),
+        })
+    }
+
+    /// The output file, or the next shard.
+    fn next_file_path(&self) -> PathBuf {
+        match self.shard_size {
+            Some(_) => {
+                let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+                let mut file_name = format!("{stem}-{:05}", self.shards.len());
+                if let Some(extension) = self.path.extension() {
+                    file_name = format!("{file_name}.{}", extension.to_string_lossy());
+                }
+                self.path.with_file_name(file_name)
+            }
+            None => self.path.clone(),
+        }
+    }
+
+    /// Creates the output file, or the next shard, and reserves room for its header.
+    fn create_file(&self) -> anyhow::Result<OutputFile> {
+        let path = self.next_file_path();
+        let temporary = temporary_path(&path);
+        let file = File::create(&temporary)
+            .with_context(|| format!("while opening {}", temporary.display()))?;
+        let mut file = io::BufWriter::new(file);
+        file.write_all(&vec![0; self.header.encode().len()])?;
+        Ok(OutputFile { path, file, rows: 0, checksum: crc32fast::Hasher::new() })
+    }
+
+    /// Writes the header of the file being written now that its number of embeddings
+    /// and their checksum are known, and renames it.
+    fn finish_file(&mut self) -> anyhow::Result<()> {
+        let Some(OutputFile { path, file, rows, checksum }) = self.current.take() else {
+            return Ok(());
+        };
+        self.header.set_shape(rows, self.dimensions);
+        self.header.set_checksum(checksum.finalize());
+        let mut file = file.into_inner()?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&self.header.encode())?;
+        file.sync_data()?;
+        let temporary = temporary_path(&path);
+        fs::rename(&temporary, &path).with_context(|| {
+            format!("while renaming {} into {}", temporary.display(), path.display())
+        })?;
+        let path = path.file_name().map_or(path.clone(), PathBuf::from);
+        self.shards.push(Shard { path, rows });
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<Written> {
+        self.finish_file()?;
+        let rows = self.shards.iter().map(|shard| shard.rows).sum();
+        let path = match self.shard_size {
+            Some(_) => {
+                let manifest = ShardManifest {
+                    name: self.name.clone(),
+                    dimensions: self.dimensions,
+                    rows,
+                    shards: self.shards.clone(),
+                };
+                let path = self.manifest_path();
+                write_atomically(&path, toml::to_string(&manifest)?.as_bytes())?;
+                path
+            }
+            None => self.path.clone(),
+        };
+        let attributes = match self.attributes {
+            Some((path, file, _)) => {
+                file.into_inner()?.sync_data()?;
+                fs::rename(temporary_path(&path), &path)?;
+                Some(path)
+            }
+            None => None,
+        };
+        Ok(Written { rows, path, shards: self.shards.len(), attributes })
+    }
+}
+
+/// Opens the temporary file of an interrupted conversion and truncates it to the length
+/// recorded in the progress, the bytes written after the last checkpoint are dropped.
+/// The file is created if there is nothing to resume from.
+fn reopen(path: &Path, len: u64) -> anyhow::Result<File> {
+    if len == 0 {
+        return File::create(path).with_context(|| format!("while opening {}", path.display()));
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("while reopening {} to resume the conversion", path.display()))?;
+    let actual = file.metadata()?.len();
+    ensure!(
+        actual >= len,
+        "{} is {actual} bytes long but {len} bytes were written before the interruption",
+        path.display()
+    );
+    file.set_len(len)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
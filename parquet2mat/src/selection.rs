@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::output::MatrixWriter;
+
+/// Picks the embeddings written in the output among the valid ones, in order: the
+/// duplicates are removed, only the first `limit` embeddings are kept and `sample`
+/// of them are then picked at random.
+pub struct Selection {
+    limit: Option<usize>,
+    /// The hashes of the embeddings already seen, when deduplicating.
+    seen: Option<(HashSet<u128>, [RandomState; 2])>,
+    reservoir: Option<Reservoir>,
+    /// The number of embeddings kept before sampling them.
+    pub kept: usize,
+    pub duplicates: usize,
+}
+
+/// A uniform sample of the embeddings, with their attributes, kept in memory until the end.
+struct Reservoir {
+    size: usize,
+    rng: StdRng,
+    /// The index of every picked embedding, to write them in their original order.
+    picked: Vec<(usize, Vec<u8>, Vec<u8>)>,
+}
+
+impl Selection {
+    pub fn new(
+        limit: Option<usize>,
+        sample: Option<usize>,
+        seed: u64,
+        deduplicate: bool,
+    ) -> Selection {
+        Selection {
+            limit,
+            seen: deduplicate.then(|| (HashSet::new(), [RandomState::new(), RandomState::new()])),
+            reservoir: sample.map(|size| Reservoir {
+                size,
+                rng: StdRng::seed_from_u64(seed),
+                picked: Vec::with_capacity(size),
+            }),
+            kept: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Whether the `limit` is reached and the following embeddings can be ignored.
+    pub fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.kept >= limit)
+    }
+
+    /// Writes the embedding, or keeps it in the sample, unless it is a duplicate or the limit is reached.
+    pub fn push(
+        &mut self,
+        vector: &[u8],
+        attributes: &[u8],
+        writer: &mut MatrixWriter,
+    ) -> anyhow::Result<()> {
+        if self.is_full() {
+            return Ok(());
+        }
+        if let Some((seen, [low, high])) = &mut self.seen {
+            // Two independent 64-bit hashes, a collision between two different vectors is unlikely enough.
+            let hash = (low.hash_one(vector) as u128) << 64 | high.hash_one(vector) as u128;
+            if !seen.insert(hash) {
+                self.duplicates += 1;
+                return Ok(());
+            }
+        }
+        let index = self.kept;
+        self.kept += 1;
+        match &mut self.reservoir {
+            // Algorithm R, every embedding ends up in the sample with the same probability.
+            Some(reservoir) if reservoir.picked.len() < reservoir.size => {
+                reservoir.picked.push((index, vector.to_vec(), attributes.to_vec()))
+            }
+            Some(reservoir) => {
+                let position = reservoir.rng.gen_range(0..=index);
+                if position < reservoir.size {
+                    reservoir.picked[position] = (index, vector.to_vec(), attributes.to_vec());
+                }
+            }
+            None => writer.write(vector, attributes)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the sampled embeddings, in the order they were read.
+    pub fn finish(self, writer: &mut MatrixWriter) -> anyhow::Result<()> {
+        if let Some(mut reservoir) = self.reservoir {
+            reservoir.picked.sort_unstable_by_key(|(index, _, _)| *index);
+            for (_, vector, attributes) in reservoir.picked {
+                writer.write(&vector, &attributes)?;
+            }
+        }
+        Ok(())
+    }
+}